@@ -0,0 +1,390 @@
+//! structs for deserializing the response of an aggregate query
+//! (aggregate.json)
+//!
+//! The response is made up of:
+//! - axes: one per query axis. Axis 0 is always Measures,
+//!   the rest are the drilldowns in the order given.
+//! - axis_dimensions: description of the dimension on each axis
+//! - values: nested arrays of cell values. The outermost array is
+//!   the last axis, the innermost is axis 0 (measures).
+//!
+//! Values are flattened on deserialization, so that rows can be
+//! iterated without caring about the number of axes.
+
+use serde::{de, Deserialize, Deserializer};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+
+use schema::Key;
+
+#[derive(Debug, Deserialize)]
+pub struct Aggregation {
+    pub axes: Vec<Axis>,
+    pub axis_dimensions: Vec<AxisDimension>,
+    #[serde(deserialize_with = "flatten_values")]
+    values: Vec<Option<f64>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Axis {
+    pub members: Vec<Member>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AxisDimension {
+    pub name: String,
+    pub caption: String,
+    #[serde(rename = "type", default)]
+    pub dim_type: String,
+    #[serde(default)]
+    pub level: String,
+    #[serde(default)]
+    pub level_depth: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Member {
+    pub name: String,
+    pub full_name: String,
+    pub caption: String,
+    #[serde(default)]
+    pub key: Option<Key>,
+    #[serde(default)]
+    pub depth: u32,
+    #[serde(default)]
+    pub level_name: String,
+    #[serde(default)]
+    pub ancestors: Vec<Member>,
+    #[serde(default)]
+    pub properties: HashMap<String, Value>,
+}
+
+/// One cell of the result for each measure, for
+/// one combination of drilldown members.
+#[derive(Debug)]
+pub struct Row<'a> {
+    pub members: Vec<&'a Member>,
+    pub values: Vec<Option<f64>>,
+}
+
+impl Aggregation {
+    /// Members of axis 0
+    pub fn measures(&self) -> &[Member] {
+        self.axes.first()
+            .map(|axis| axis.members.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Axis dimensions of all axes except Measures
+    pub fn drilldowns(&self) -> &[AxisDimension] {
+        if self.axis_dimensions.is_empty() {
+            &[]
+        } else {
+            &self.axis_dimensions[1..]
+        }
+    }
+
+    /// All values, flattened with the last axis outermost.
+    pub fn values(&self) -> &[Option<f64>] {
+        &self.values
+    }
+
+    /// Rows are ordered with the first drilldown varying slowest,
+    /// like a sql `GROUP BY` over the drilldowns.
+    pub fn rows<'a>(&'a self) -> Vec<Row<'a>> {
+        let measure_count = self.measures().len();
+        let drill_axes = if self.axes.len() > 1 { &self.axes[1..] } else { &[] };
+
+        // a drilldown with no members means no rows at all.
+        if measure_count == 0 || drill_axes.iter().any(|axis| axis.members.is_empty()) {
+            return Vec::new();
+        }
+
+        // strides into the flattened values, in axis order.
+        // axis 0 has stride 1, each axis after is multiplied by
+        // the length of the axis before it.
+        let mut strides = Vec::with_capacity(self.axes.len());
+        let mut stride = 1;
+        for axis in &self.axes {
+            strides.push(stride);
+            stride *= axis.members.len();
+        }
+
+        let row_count = drill_axes.iter().map(|axis| axis.members.len()).product();
+        let mut rows = Vec::with_capacity(row_count);
+
+        // index for each drilldown axis; last drilldown varies fastest.
+        let mut idx = vec![0; drill_axes.len()];
+
+        for _ in 0..row_count {
+            let offset: usize = idx.iter()
+                .enumerate()
+                .map(|(i, member_idx)| member_idx * strides[i + 1])
+                .sum();
+
+            let members = idx.iter()
+                .enumerate()
+                .map(|(i, member_idx)| &drill_axes[i].members[*member_idx])
+                .collect();
+
+            let values = (0..measure_count)
+                .map(|i| self.values.get(offset + i).cloned().unwrap_or(None))
+                .collect();
+
+            rows.push(Row { members, values });
+
+            // increment idx like an odometer
+            for i in (0..idx.len()).rev() {
+                idx[i] += 1;
+                if idx[i] < drill_axes[i].members.len() {
+                    break;
+                }
+                idx[i] = 0;
+            }
+        }
+
+        rows
+    }
+}
+
+/// Aligned table, one column per drilldown (member captions)
+/// followed by one column per measure.
+impl fmt::Display for Aggregation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut header: Vec<String> = self.drilldowns().iter()
+            .map(|dim| dim.caption.clone())
+            .collect();
+        header.extend(self.measures().iter().map(|mea| mea.caption.clone()));
+
+        let drill_count = self.drilldowns().len();
+
+        let body: Vec<Vec<String>> = self.rows().iter()
+            .map(|row| {
+                let mut cells: Vec<String> = row.members.iter()
+                    .map(|member| member.caption.clone())
+                    .collect();
+                cells.extend(row.values.iter().map(|value| format_value(*value)));
+                cells
+            })
+            .collect();
+
+        let mut widths: Vec<usize> = header.iter().map(|cell| cell.chars().count()).collect();
+        for row in &body {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(cell.chars().count());
+            }
+        }
+
+        // drilldowns left-aligned, measures right-aligned
+        let write_row = |f: &mut fmt::Formatter, cells: &[String]| -> fmt::Result {
+            let line = cells.iter()
+                .enumerate()
+                .map(|(i, cell)| {
+                    if i < drill_count {
+                        format!("{:<width$}", cell, width = widths[i])
+                    } else {
+                        format!("{:>width$}", cell, width = widths[i])
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("  ");
+            writeln!(f, "{}", line.trim_end())
+        };
+
+        write_row(f, &header)?;
+
+        let rule = widths.iter()
+            .map(|width| "-".repeat(*width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(f, "{}", rule)?;
+
+        for row in &body {
+            write_row(f, row)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn format_value(value: Option<f64>) -> String {
+    match value {
+        Some(v) if v.fract() == 0.0 && v.abs() < 1e15 => format!("{}", v as i64),
+        Some(v) => format!("{}", v),
+        None => "".to_owned(),
+    }
+}
+
+/// Flattens the nested values arrays. Nulls (empty cells)
+/// become None; numbers which mondrian sends as strings
+/// (e.g. "NaN") are parsed, and any other string is an error
+/// rather than being shown as an empty cell.
+fn flatten_values<'de, D>(deserializer: D) -> Result<Vec<Option<f64>>, D::Error>
+    where D: Deserializer<'de>
+{
+    fn flatten<E: de::Error>(value: Value, out: &mut Vec<Option<f64>>) -> Result<(), E> {
+        match value {
+            Value::Array(values) => {
+                for v in values {
+                    flatten(v, out)?;
+                }
+            },
+            Value::Null => out.push(None),
+            Value::Number(n) => out.push(n.as_f64()),
+            Value::String(s) => match s.parse() {
+                Ok(v) => out.push(Some(v)),
+                Err(_) => return Err(de::Error::custom(format!("invalid aggregate value: {:?}", s))),
+            },
+            v => return Err(de::Error::custom(format!("invalid aggregate value: {}", v))),
+        }
+        Ok(())
+    }
+
+    let value = Value::deserialize(deserializer)?;
+    let mut out = Vec::new();
+    flatten(value, &mut out)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json;
+
+    fn member(name: &str, key: &str) -> String {
+        format!(r#"{{
+            "name": "{0}",
+            "full_name": "[Geography].[{0}]",
+            "caption": "{0}",
+            "all_member?": false,
+            "drillable?": false,
+            "depth": 1,
+            "key": "{1}",
+            "num_children": 0,
+            "parent_name": "All",
+            "level_name": "County",
+            "children": []
+        }}"#, name, key)
+    }
+
+    fn measure(name: &str) -> String {
+        format!(r#"{{
+            "name": "{0}",
+            "full_name": "[Measures].[{0}]",
+            "caption": "{0}",
+            "all_member?": false,
+            "drillable?": false,
+            "depth": 0,
+            "key": "{0}",
+            "num_children": 0,
+            "parent_name": null,
+            "level_name": "MeasuresLevel",
+            "children": []
+        }}"#, name)
+    }
+
+    fn two_drilldowns() -> Aggregation {
+        let json = format!(r#"{{
+            "axes": [
+                {{ "members": [{}, {}] }},
+                {{ "members": [{}, {}] }},
+                {{ "members": [{}, {}, {}] }}
+            ],
+            "axis_dimensions": [
+                {{ "name": "Measures", "caption": "Measures", "type": "measures", "level": "MeasuresLevel", "level_depth": 0 }},
+                {{ "name": "Geography", "caption": "Geography", "type": "standard", "level": "County", "level_depth": 1 }},
+                {{ "name": "Year", "caption": "Year", "type": "time", "level": "Year", "level_depth": 1 }}
+            ],
+            "values": [
+                [[1, 2], [3, 4]],
+                [[5, 6], [7, 8]],
+                [[9, null], [11, 12]]
+            ]
+        }}"#,
+            measure("Dollars Sum"), measure("Count"),
+            member("Adams", "1"), member("Brown", "2"),
+            member("2014", "2014"), member("2015", "2015"), member("2016", "2016"),
+        );
+
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn test_aggregation_shape() {
+        let agg = two_drilldowns();
+
+        assert_eq!(agg.measures().len(), 2);
+        assert_eq!(agg.drilldowns().len(), 2);
+        assert_eq!(agg.drilldowns()[1].level, "Year");
+        assert_eq!(agg.values().len(), 12);
+    }
+
+    #[test]
+    fn test_aggregation_rows() {
+        let agg = two_drilldowns();
+        let rows = agg.rows();
+
+        let rows: Vec<(String, String, Vec<Option<f64>>)> = rows.into_iter()
+            .map(|row| (row.members[0].name.clone(), row.members[1].name.clone(), row.values))
+            .collect();
+
+        assert_eq!(rows, vec![
+            ("Adams".to_owned(), "2014".to_owned(), vec![Some(1.0), Some(2.0)]),
+            ("Adams".to_owned(), "2015".to_owned(), vec![Some(5.0), Some(6.0)]),
+            ("Adams".to_owned(), "2016".to_owned(), vec![Some(9.0), None]),
+            ("Brown".to_owned(), "2014".to_owned(), vec![Some(3.0), Some(4.0)]),
+            ("Brown".to_owned(), "2015".to_owned(), vec![Some(7.0), Some(8.0)]),
+            ("Brown".to_owned(), "2016".to_owned(), vec![Some(11.0), Some(12.0)]),
+        ]);
+    }
+
+    #[test]
+    fn test_aggregation_table() {
+        let agg = two_drilldowns();
+        let table = agg.to_string();
+        let mut lines = table.lines();
+
+        assert_eq!(lines.next(), Some("Geography  Year  Dollars Sum  Count"));
+        assert_eq!(lines.next(), Some("---------  ----  -----------  -----"));
+        assert_eq!(lines.next(), Some("Adams      2014            1      2"));
+        assert_eq!(lines.nth(1), Some("Adams      2016            9"));
+        assert_eq!(lines.count(), 3);
+    }
+
+    #[test]
+    fn test_aggregation_no_drilldown() {
+        let json = format!(r#"{{
+            "axes": [ {{ "members": [{}] }} ],
+            "axis_dimensions": [
+                {{ "name": "Measures", "caption": "Measures", "type": "measures", "level": "MeasuresLevel", "level_depth": 0 }}
+            ],
+            "values": [42.5]
+        }}"#, measure("Dollars Sum"));
+
+        let agg: Aggregation = serde_json::from_str(&json).unwrap();
+        let rows = agg.rows();
+
+        assert_eq!(rows.len(), 1);
+        assert!(rows[0].members.is_empty());
+        assert_eq!(rows[0].values, vec![Some(42.5)]);
+    }
+
+    #[test]
+    fn test_aggregation_string_values() {
+        let json = |values: &str| format!(r#"{{
+            "axes": [ {{ "members": [{}, {}] }} ],
+            "axis_dimensions": [
+                {{ "name": "Measures", "caption": "Measures", "type": "measures", "level": "MeasuresLevel", "level_depth": 0 }}
+            ],
+            "values": {}
+        }}"#, measure("Dollars Sum"), measure("Count"), values);
+
+        let agg: Aggregation = serde_json::from_str(&json(r#"["NaN", "12.5"]"#)).unwrap();
+        assert!(agg.values()[0].unwrap().is_nan());
+        assert_eq!(agg.values()[1], Some(12.5));
+
+        let err = serde_json::from_str::<Aggregation>(&json(r#"["N/A", 1]"#)).unwrap_err();
+        assert!(err.to_string().contains(r#"invalid aggregate value: "N/A""#), "{}", err);
+    }
+}
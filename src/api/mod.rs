@@ -1,21 +1,55 @@
 /// Interface to mondrian rest api
 
+#[macro_use]
 pub mod names;
+pub(crate) mod parse;
 
 use failure::Error;
-use reqwest::{self, Url};
+use reqwest::{self, StatusCode, Url};
 use serde_json;
+use std::convert::TryFrom;
 use std::fmt;
 use std::str::FromStr;
+use url::percent_encoding::percent_decode;
 
 pub use self::names::{LevelName, Drilldown, Measure, Cut, Property};
-
-#[derive(Debug, Clone, PartialEq)]
+use error::CliError;
+use schema::CubeDescription;
+
+/// Serializes to a stable schema, e.g. in json:
+///
+/// ```text
+/// {
+///   "base_url": "http://localhost:5000",
+///   "cube": "exports",
+///   "drilldowns": ["[Geography].[Geography].[County]"],
+///   "named_sets": [],
+///   "measures": ["Dollars Sum"],
+///   "cuts": ["[Year].[Year].[Year].&[2016]"],
+///   "properties": [],
+///   "debug": false,
+///   "parents": false,
+///   "nonempty": false,
+///   "distinct": false,
+///   "sparse": false,
+///   "format": "csv"
+/// }
+/// ```
+///
+/// Names are full names, as in the url. `base_url`, `cube` and
+/// `members` are left out when not set; when deserializing, any
+/// missing field is the default, and unknown fields are an error.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct QueryBuilder {
+    #[serde(skip_serializing_if = "String::is_empty")]
     base_url: String,
+    #[serde(rename = "cube", skip_serializing_if = "Option::is_none")]
     cube_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     members: Option< LevelName>,
     drilldowns: Vec<Drilldown>,
+    named_sets: Vec<String>,
     measures: Vec<Measure>,
     cuts: Vec<Cut>,
     properties: Vec<Property>,
@@ -34,6 +68,7 @@ impl Default for QueryBuilder {
             cube_name: None,
             members: None,
             drilldowns: Vec::new(),
+            named_sets: Vec::new(),
             measures: Vec::new(),
             cuts: Vec::new(),
             properties: Vec::new(),
@@ -49,6 +84,11 @@ impl Default for QueryBuilder {
 
 /// Builder pattern
 impl QueryBuilder {
+    pub fn base_url<S: Into<String>>(&mut self, base_url: S) -> &mut Self {
+        self.base_url = base_url.into();
+        self
+    }
+
     pub fn cube<S: Into<String>>(&mut self, cube_name: S) -> &mut Self {
         self.cube_name = Some(cube_name.into());
         self
@@ -71,6 +111,18 @@ impl QueryBuilder {
         self
     }
 
+    /// Named sets are drilled down on like levels; mondrian-rest
+    /// recognizes a drilldown that is just the name of a set.
+    pub fn named_set<S: Into<String>>(&mut self, named_set: S) -> &mut Self {
+        self.named_sets.push(named_set.into());
+        self
+    }
+
+    pub fn named_sets(&mut self, named_sets: Vec<String>) -> &mut Self {
+        self.named_sets.extend_from_slice(&named_sets);
+        self
+    }
+
     pub fn measure(&mut self, measure: Measure) -> &mut Self {
         self.measures.push(measure);
         self
@@ -131,6 +183,71 @@ impl QueryBuilder {
         self
     }
 
+    pub fn response_format(&self) -> &ResponseFormat {
+        &self.format
+    }
+
+    /// False for a query not yet given a base url, e.g. one
+    /// deserialized without `base_url`
+    pub fn has_base_url(&self) -> bool {
+        !self.base_url.is_empty()
+    }
+
+    /// Url for the description of the cube being queried
+    pub fn cube_description_url(&self) -> Result<Url, Error> {
+        let cube_name = match self.cube_name {
+            Some(ref cube_name) => cube_name.clone(),
+            None => return Err(CliError::Config("Cube name is required for cube description".to_owned()).into()),
+        };
+
+        let mut req = query(self.base_url.clone());
+        req.cube(cube_name);
+        req.url()
+    }
+
+    /// Checks every name in the query against the cube schema,
+    /// so that typos are caught before the query is sent.
+    /// All unknown names are reported together.
+    pub fn validate(&self, cube: &CubeDescription) -> Result<(), Error> {
+        let mut problems = Vec::new();
+
+        for drilldown in &self.drilldowns {
+            if let Err(msg) = cube.check_level(drilldown.level_name()) {
+                problems.push(format!("drilldown {}: {}", drilldown, msg));
+            }
+        }
+        for named_set in &self.named_sets {
+            if let Err(msg) = cube.check_named_set(named_set) {
+                problems.push(format!("named set {}: {}", named_set, msg));
+            }
+        }
+        for measure in &self.measures {
+            if let Err(msg) = cube.check_measure(measure.name()) {
+                problems.push(format!("measure {}: {}", measure, msg));
+            }
+        }
+        for cut in &self.cuts {
+            if let Err(msg) = cube.check_level(cut.level_name()) {
+                problems.push(format!("cut {}: {}", cut, msg));
+            }
+        }
+        for property in &self.properties {
+            if let Err(msg) = cube.check_property(property.level_name(), property.property()) {
+                problems.push(format!("property {}: {}", property, msg));
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(CliError::Name(format!(
+                "Query does not match schema of cube {:?}:\n  {}",
+                cube.name,
+                problems.join("\n  ")
+            )).into());
+        }
+
+        Ok(())
+    }
+
     /// The other finalizer
     /// return the url
     pub fn url(&self) -> Result<Url, Error> {
@@ -145,15 +262,16 @@ impl QueryBuilder {
 
         if let Some(ref members) = self.members {
             if self.cube_name.is_none() {
-                bail!("Members call requires a cube name");
+                return Err(CliError::Config("Members call requires a cube name".to_owned()).into());
             }
 
             if !self.drilldowns.is_empty() ||
+                !self.named_sets.is_empty() ||
                 !self.measures.is_empty() ||
                 !self.cuts.is_empty() ||
                 !self.properties.is_empty()
             {
-                bail!("Members call should not include query parameters");
+                return Err(CliError::Config("Members call should not include query parameters".to_owned()).into());
             }
 
             let mut base_url = self.base_url.clone();
@@ -204,12 +322,13 @@ impl QueryBuilder {
             url = url.join(&cube_name)?;
 
             // At this point, only add aggregate etc if
-            // there is drilldown and measure. Otherwise
-            // pass through (it's just asking for a description
-            // of one cube)
-            if !self.drilldowns.is_empty() && !self.measures.is_empty() {
+            // there is drilldown (or named set) and measure.
+            // Otherwise pass through (it's just asking for a
+            // description of one cube)
+            let has_drilldown = !self.drilldowns.is_empty() || !self.named_sets.is_empty();
+            if has_drilldown && !self.measures.is_empty() {
                 url = url.join(
-                    format!("aggregate.{}", self.format).as_str()
+                    format!("aggregate.{}", self.format.request_format()).as_str()
                 )?;
 
                 // add all query parameters
@@ -218,6 +337,10 @@ impl QueryBuilder {
                     url.query_pairs_mut()
                         .append_pair("drilldown[]", &drilldown.to_string());
                 }
+                for named_set in &self.named_sets {
+                    url.query_pairs_mut()
+                        .append_pair("drilldown[]", named_set);
+                }
                 for measure in &self.measures {
                     url.query_pairs_mut()
                         .append_pair("measures[]", &measure.to_string());
@@ -247,11 +370,12 @@ impl QueryBuilder {
             // error. Otherwise, just pass (just asking for
             // a description of all cubes)
             if !self.drilldowns.is_empty() ||
+                !self.named_sets.is_empty() ||
                 !self.measures.is_empty() ||
                 !self.cuts.is_empty() ||
                 !self.properties.is_empty()
             {
-                bail!("Cube name is required for query");
+                return Err(CliError::Config("Cube name is required for query".to_owned()).into());
             }
         }
 
@@ -260,11 +384,161 @@ impl QueryBuilder {
 
 }
 
+/// Parses a url generated by `url()` (or by any other
+/// mondrian-rest client) back into a QueryBuilder.
+///
+/// The base url is everything before the `cubes` path segment.
+impl<'a> TryFrom<&'a Url> for QueryBuilder {
+    type Error = Error;
+
+    fn try_from(url: &'a Url) -> Result<Self, Self::Error> {
+        parse_url(url, None)
+    }
+}
+
+impl QueryBuilder {
+    /// Parses a url like `try_from`, but with the cube's schema a
+    /// `drilldown[]` is a named set exactly when the cube has a
+    /// named set of that name (which may contain a '.').
+    pub fn from_url_with_cube(url: &Url, cube: &CubeDescription) -> Result<Self, Error> {
+        parse_url(url, Some(cube))
+    }
+}
+
+fn parse_url(url: &Url, cube: Option<&CubeDescription>) -> Result<QueryBuilder, Error> {
+    let segments = url.path_segments()
+        .map(|segments| {
+            segments
+                .map(|segment| {
+                    percent_decode(segment.as_bytes())
+                        .decode_utf8()
+                        .map(|segment| segment.into_owned())
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .unwrap_or_else(|| Ok(Vec::new()))?;
+
+    let cubes_idx = match segments.iter().position(|segment| segment == "cubes") {
+        Some(idx) => idx,
+        None => return Err(config_err(format!("Url {} is not a mondrian-rest url: no cubes path", url))),
+    };
+
+    let mut base_url = url.clone();
+    base_url.set_query(None);
+    base_url.set_fragment(None);
+    base_url.path_segments_mut()
+        .map_err(|_| config_err(format!("Url {} cannot be a base url", url)))?
+        .clear()
+        .extend(&segments[..cubes_idx])
+        .push("");
+
+    let mut builder = query(base_url.into_string());
+
+    // remove trailing empty segment, from trailing slash
+    let mut path = &segments[cubes_idx + 1..];
+    if path.last().map(|segment| segment.is_empty()).unwrap_or(false) {
+        path = &path[..path.len() - 1];
+    }
+
+    match path.len() {
+        // all cubes
+        0 => {},
+        // one cube
+        1 => { builder.cube(path[0].clone()); },
+        // aggregate query
+        2 => {
+            builder.cube(path[0].clone());
+
+            let format = match path[1].split_at(path[1].find('.').unwrap_or(0)) {
+                ("aggregate", ext) if !ext.is_empty() => ext[1..].parse()?,
+                _ => return Err(config_err(format!("Unknown path {:?} in url {}, expected aggregate", path[1], url))),
+            };
+            builder.format(format);
+
+            for (k, v) in url.query_pairs() {
+                match k.as_ref() {
+                    "drilldown[]" if is_named_set(&v, cube) => { builder.named_set(v.into_owned()); },
+                    "drilldown[]" => { builder.drilldown(v.parse()?); },
+                    "measures[]" => { builder.measure(v.parse()?); },
+                    "cut[]" => { builder.cut(v.parse()?); },
+                    "properties[]" => { builder.property(v.parse()?); },
+                    "debug" => { builder.debug(parse_bool(&k, &v)?); },
+                    "parents" => { builder.parents(parse_bool(&k, &v)?); },
+                    "nonempty" => { builder.nonempty(parse_bool(&k, &v)?); },
+                    "distinct" => { builder.distinct(parse_bool(&k, &v)?); },
+                    "sparse" => { builder.sparse(parse_bool(&k, &v)?); },
+                    _ => return Err(config_err(format!("Unsupported query parameter {:?} in url {}", k, url))),
+                }
+            }
+        },
+        // members
+        8 if path[1] == "dimensions" &&
+            path[3] == "hierarchies" &&
+            path[5] == "levels" &&
+            path[7] == "members" =>
+        {
+            builder.cube(path[0].clone())
+                .members(LevelName::new(path[2].clone(), path[4].clone(), path[6].clone()));
+        },
+        _ => return Err(config_err(format!("Url {} is not a recognized mondrian-rest url", url))),
+    }
+
+    Ok(builder)
+}
+
+impl TryFrom<Url> for QueryBuilder {
+    type Error = Error;
+
+    fn try_from(url: Url) -> Result<Self, Self::Error> {
+        QueryBuilder::try_from(&url)
+    }
+}
+
+impl FromStr for QueryBuilder {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let url = Url::parse(s)?;
+        QueryBuilder::try_from(&url)
+    }
+}
+
+fn config_err(msg: String) -> Error {
+    CliError::Config(msg).into()
+}
+
+/// With the cube's schema, a drilldown is a named set if the cube
+/// has a named set of that name. Without it, a drilldown that
+/// isn't a valid level name is taken to be a named set.
+fn is_named_set(drilldown: &str, cube: Option<&CubeDescription>) -> bool {
+    match cube {
+        Some(cube) => cube.has_named_set(drilldown),
+        None => drilldown.parse::<LevelName>().is_err(),
+    }
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, Error> {
+    value.parse()
+        .map_err(|_| config_err(format!("{:?} is not a valid value for {}; expected true or false", value, key)))
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ResponseFormat {
     Json,
     JsonRecords,
     Csv,
+    Table,
+}
+
+impl ResponseFormat {
+    /// The format to request from the server. Table is
+    /// rendered locally from json.
+    pub fn request_format(&self) -> &ResponseFormat {
+        match *self {
+            ResponseFormat::Table => &ResponseFormat::Json,
+            ref format => format,
+        }
+    }
 }
 
 impl FromStr for ResponseFormat {
@@ -276,11 +550,14 @@ impl FromStr for ResponseFormat {
             "json" => Ok(Json),
             "jsonrecords" => Ok(JsonRecords),
             "csv" => Ok(Csv),
-            _ => Err(format_err!("{:?} is not a valid response format", s))
+            "table" => Ok(Table),
+            _ => Err(config_err(format!("{:?} is not a valid response format", s)))
         }
     }
 }
 
+serde_with_str!(ResponseFormat);
+
 impl fmt::Display for ResponseFormat {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use self::ResponseFormat::*;
@@ -288,6 +565,7 @@ impl fmt::Display for ResponseFormat {
             Json => write!(f, "json"),
             JsonRecords => write!(f, "jsonrecords"),
             Csv => write!(f, "csv"),
+            Table => write!(f, "table"),
         }
     }
 }
@@ -308,33 +586,46 @@ pub(crate) fn add_trailing_slash(s: &mut String) {
     }
 }
 
-pub(crate) fn format_backtrace(s: String) -> String {
-    let try_de: Result<MonError, _> = serde_json::from_str(&s);
+/// Categorizes an error response. Mondrian runtime errors
+/// are json; anything else is a generic server error.
+pub(crate) fn server_error(status: StatusCode, body: String) -> CliError {
+    let try_de: Result<MonError, _> = serde_json::from_str(&body);
 
-    // Need one more format for runtime errors that are caught.
-    // Which appear to be {"error": "error description"}
     if let Ok(err) = try_de {
-        // for query runtime errors that are uncaught
-        err.error[0].clone()
+        let msg = match err.error {
+            // for query runtime errors that are uncaught
+            MonErrorMsg::Backtrace(lines) => lines.into_iter().next().unwrap_or_default(),
+            // for runtime errors that are caught
+            MonErrorMsg::Message(msg) => msg,
+        };
+        CliError::Mondrian { status, msg }
     } else {
         // for flush runtime errors
-        s.lines()
+        let msg = body.lines()
             .take(2)
             .collect::<Vec<_>>()
-            .join("\n")
+            .join("\n");
+        CliError::Server { status, msg }
     }
-
 }
 
 #[derive(Debug, Deserialize)]
 struct MonError {
-    error: Vec<String>,
+    error: MonErrorMsg,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum MonErrorMsg {
+    Backtrace(Vec<String>),
+    Message(String),
 }
 
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use test_support::EXPORTS_JSON;
 
     #[test]
     fn test_add_trailing_slash() {
@@ -347,4 +638,162 @@ mod test {
         assert_eq!(test, "test/".to_owned());
         assert_eq!(test1, "test1/".to_owned());
     }
+
+    #[test]
+    fn test_url_round_trip() {
+        let mut req = query("http://localhost:5000/".to_owned());
+        req.cube("Exports by Port")
+            .drilldown(Drilldown::new("Geography", "Geography", "County"))
+            .drilldown(Drilldown::new("Year", "Year", "Year"))
+            .named_set("Big States")
+            .measure(Measure::new("Dollars Sum"))
+            .measure(Measure::new("Count"))
+            .cut(Cut::new("Year", "Year", "Year", vec!["2015", "2016"]))
+            .cut(Cut::new("Category", "Category", "Category", vec!["1"]))
+            .property(Property::new("Geography", "Geography", "County", "name_en"))
+            .parents(true)
+            .sparse(true)
+            .format(ResponseFormat::Csv);
+
+        let url = req.url().unwrap();
+        let parsed = url.as_str().parse::<QueryBuilder>().unwrap();
+
+        assert_eq!(req, parsed);
+        assert_eq!(url, parsed.url().unwrap());
+    }
+
+    #[test]
+    fn test_url_round_trip_members() {
+        let mut req = query("http://localhost:5000/mondrian/".to_owned());
+        req.cube("exports")
+            .members(LevelName::new("Geography", "Geography", "County"));
+
+        let url = req.url().unwrap();
+        let parsed = QueryBuilder::try_from(url.clone()).unwrap();
+
+        assert_eq!(req, parsed);
+        assert_eq!(url, parsed.url().unwrap());
+    }
+
+    #[test]
+    fn test_from_url() {
+        let url = "http://localhost:5000/cubes/exports/aggregate.json?\
+            drilldown%5B%5D=%5BGeography%5D.%5BCounty%5D&\
+            measures%5B%5D=Dollars+Sum&\
+            cut%5B%5D=%5BYear%5D.%5BYear%5D.%5BYear%5D.%26%5B2016%5D&\
+            nonempty=true";
+        let parsed = url.parse::<QueryBuilder>().unwrap();
+
+        let mut req = query("http://localhost:5000/".to_owned());
+        req.cube("exports")
+            .drilldown(Drilldown::new("Geography", "Geography", "County"))
+            .measure(Measure::new("Dollars Sum"))
+            .cut(Cut::new("Year", "Year", "Year", vec!["2016"]))
+            .nonempty(true);
+
+        assert_eq!(req, parsed);
+    }
+
+    #[test]
+    fn test_named_set_url() {
+        let mut req = query("http://localhost:5000/".to_owned());
+        req.cube("exports")
+            .named_set("Big States")
+            .measure(Measure::new("Dollars Sum"));
+
+        let url = req.url().unwrap();
+        assert_eq!(
+            url.as_str(),
+            "http://localhost:5000/cubes/exports/aggregate.json?\
+            drilldown%5B%5D=Big+States&\
+            measures%5B%5D=Dollars+Sum&\
+            debug=false&parents=false&nonempty=false&distinct=false&sparse=false"
+        );
+        assert_eq!(req, url.as_str().parse::<QueryBuilder>().unwrap());
+    }
+
+    #[test]
+    fn test_named_set_url_with_cube() {
+        let cube: CubeDescription = serde_json::from_str(&EXPORTS_JSON.replace("Big States", "St. Louis Area")).unwrap();
+        let url = Url::parse(
+            "http://localhost:5000/cubes/exports/aggregate.json?\
+            drilldown%5B%5D=St.+Louis+Area&\
+            drilldown%5B%5D=Geography.State&\
+            measures%5B%5D=Count"
+        ).unwrap();
+
+        let mut expected = query("http://localhost:5000/".to_owned());
+        expected.cube("exports")
+            .named_set("St. Louis Area")
+            .drilldown("Geography.State".parse().unwrap())
+            .measure(Measure::new("Count"));
+        assert_eq!(QueryBuilder::from_url_with_cube(&url, &cube).unwrap(), expected);
+
+        // without the schema, a named set with a '.' looks like a level
+        let parsed = QueryBuilder::try_from(&url).unwrap();
+        assert!(parsed.named_sets.is_empty());
+        assert_eq!(parsed.drilldowns.len(), 2);
+
+        // a bare level name isn't taken for a named set
+        let url = Url::parse("http://localhost:5000/cubes/exports/aggregate.json?drilldown%5B%5D=State").unwrap();
+        assert!(QueryBuilder::from_url_with_cube(&url, &cube).is_err());
+    }
+
+    #[test]
+    fn test_serde() {
+        let mut req = query("http://localhost:5000/".to_owned());
+        req.cube("exports")
+            .drilldown("Geography.County".parse().unwrap())
+            .measure(Measure::new("Dollars Sum"))
+            .cut("Year.Year.2015,2016".parse().unwrap())
+            .property("Geography.County.name_en".parse().unwrap())
+            .parents(true)
+            .format(ResponseFormat::Csv);
+
+        let json = serde_json::to_string_pretty(&req).unwrap();
+        assert_eq!(json, r#"{
+  "base_url": "http://localhost:5000/",
+  "cube": "exports",
+  "drilldowns": [
+    "[Geography].[Geography].[County]"
+  ],
+  "named_sets": [],
+  "measures": [
+    "Dollars Sum"
+  ],
+  "cuts": [
+    "{[Year].[Year].[Year].&[2015],[Year].[Year].[Year].&[2016]}"
+  ],
+  "properties": [
+    "[Geography].[Geography].[County].[name_en]"
+  ],
+  "debug": false,
+  "parents": true,
+  "nonempty": false,
+  "distinct": false,
+  "sparse": false,
+  "format": "csv"
+}"#);
+        assert_eq!(serde_json::from_str::<QueryBuilder>(&json).unwrap(), req);
+
+        // missing fields are defaults; short names are parsed
+        let members: QueryBuilder = serde_json::from_str(
+            r#"{ "cube": "exports", "members": "Geography.State" }"#
+        ).unwrap();
+        let mut expected = QueryBuilder::default();
+        expected.cube("exports")
+            .members(LevelName::new("Geography", "Geography", "State"));
+        assert_eq!(members, expected);
+
+        assert!(serde_json::from_str::<QueryBuilder>(r#"{ "measure": ["Count"] }"#).is_err());
+        assert!(serde_json::from_str::<QueryBuilder>(r#"{ "drilldowns": ["[Geography"] }"#).is_err());
+        assert!(serde_json::from_str::<QueryBuilder>(r#"{ "format": "xml" }"#).is_err());
+    }
+
+    #[test]
+    fn test_from_url_bad() {
+        assert!("http://localhost:5000/exports/aggregate.json".parse::<QueryBuilder>().is_err());
+        assert!("http://localhost:5000/cubes/exports/facts.json".parse::<QueryBuilder>().is_err());
+        assert!("http://localhost:5000/cubes/exports/aggregate.json?limit=10".parse::<QueryBuilder>().is_err());
+    }
 }
@@ -12,17 +12,44 @@
 // can be formatted to a string for joining to
 // a url.
 //
-// Implement FromStr to be able to easily parse
-// a small variety of names.
+// Implement FromStr to parse names following mdx
+// identifier quoting rules (see parse module), e.g.
 // - [Dimension].[Hierarchy].[Level]
 // - Dimension.Hierarchy.Level
 // - Dimension.Level
+// - Dimension.[Level.With.Periods]
 // etc.
+//
+// Serde goes through Display and FromStr, so a serialized
+// name is the same string as in a url.
 
 use failure::Error;
 use std::fmt;
 use std::str::FromStr;
 
+use error::CliError;
+use super::parse::{self, escape};
+
+/// Serialize as the Display string, deserialize with FromStr
+macro_rules! serde_with_str {
+    ($($name:ty),*) => {$(
+        impl ::serde::Serialize for $name {
+            fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> ::serde::Deserialize<'de> for $name {
+            fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let s: String = ::serde::Deserialize::deserialize(deserializer)?;
+                s.parse().map_err(::serde::de::Error::custom)
+            }
+        }
+    )*};
+}
+
+serde_with_str!(LevelName, Drilldown, Measure, Cut, Property);
+
 /// Fully qualified name of Dimension, Hierarchy, and Level
 /// Basis for other names.
 #[derive(Debug, Clone, PartialEq)]
@@ -57,10 +84,10 @@ impl LevelName {
                 level: level_name[1].clone().into(),
             })
         } else {
-            bail!(
+            Err(CliError::Name(format!(
                 "Dimension {:?} does not follow naming convention",
                 level_name.into_iter().map(|s| s.into()).collect::<Vec<String>>()
-            );
+            )).into())
         }
     }
 
@@ -79,32 +106,21 @@ impl LevelName {
 
 impl fmt::Display for LevelName {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "[{}].[{}].[{}]", self.dimension, self.hierarchy, self.level)
+        write!(
+            f,
+            "[{}].[{}].[{}]",
+            escape(&self.dimension),
+            escape(&self.hierarchy),
+            escape(&self.level),
+        )
     }
 }
 
 impl FromStr for LevelName {
     type Err = Error;
 
-    /// NAIVE IMPL, does not deal with escaped brackets,
-    /// unpaired brackets, etc. Just checks whether the
-    /// first char is a bracket to determine whether to
-    /// parse using brackets or not.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let name_iter = if s.chars().nth(0).unwrap() == '[' {
-            // check if starts with '[', then assume
-            // that this means that it's a qualified name
-            // with [] wrappers. This means that can't just
-            // split on any periods, only periods that fall
-            // outside the []
-            let pattern: &[_] = &['[', ']'];
-            let s = s.trim_matches(pattern);
-            s.split("].[")
-        } else {
-            s.split(".")
-        };
-
-        LevelName::from_vec(name_iter.collect())
+        LevelName::from_vec(parse::parse_level_name(s)?)
     }
 }
 
@@ -121,13 +137,17 @@ impl Drilldown {
     /// Names must have already been trimmed of [] delimiters.
     pub fn from_vec<S: Into<String> + Clone>(drilldown: Vec<S>) -> Result<Self, Error> 
     {
-        LevelName::from_vec(drilldown).map(|x| Drilldown(x))
+        LevelName::from_vec(drilldown).map(Drilldown)
+    }
+
+    pub fn level_name(&self) -> &LevelName {
+        &self.0
     }
 }
 
 impl fmt::Display for Drilldown {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "[{}].[{}].[{}]", self.0.dimension, self.0.hierarchy, self.0.level)
+        write!(f, "{}", self.0)
     }
 }
 
@@ -135,12 +155,13 @@ impl FromStr for Drilldown {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        s.parse::<LevelName>().map(|level_name| Drilldown(level_name))
+        s.parse::<LevelName>().map(Drilldown)
     }
 }
 
-/// Naive impl, does not check that [Measure]. is NOT
-/// prepended. But does remove brackets on FromStr
+/// Displays as the plain measure name, which is what
+/// mondrian-rest expects. FromStr accepts the plain name,
+/// [Measure], or [Measures].[Measure]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Measure(String);
 
@@ -148,6 +169,10 @@ impl Measure {
     pub fn new<S: Into<String>>(measure: S) -> Self {
         Measure(measure.into())
     }
+
+    pub fn name(&self) -> &str {
+        self.0.as_str()
+    }
 }
 
 impl fmt::Display for Measure{
@@ -160,15 +185,13 @@ impl FromStr for Measure {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let pattern: &[_] = &['[', ']'];
-        let s = s.trim_matches(pattern);
-        Ok(Measure(s.to_owned()))
+        Ok(Measure(parse::parse_measure(s)?))
     }
 }
 
-/// Note: FromStr impl aggressively left trims ampersands
-/// from the beginning of member list and from the
-/// beginning of each member
+/// Note: FromStr accepts a ',' delimited list of members
+/// in the last part (cli convenience), each optionally
+/// prefixed by '&'. Bracket member keys containing ',' or '.'
 // TODO change cut and property to LevelName
 #[derive(Debug, Clone, PartialEq)]
 pub struct Cut {
@@ -193,13 +216,15 @@ impl Cut {
     /// Names must have already been trimmed of [] delimiters.
     pub fn from_vec<S: Into<String> + Clone>(cut_level: Vec<S>, members: Vec<S>) -> Result<Self, Error> 
     {
-        ensure!(members.len() > 0, "No members found");
+        if members.is_empty() {
+            return Err(CliError::Name("No members found".to_owned()).into());
+        }
 
         // TODO get rid of clones
         Ok(LevelName::from_vec(cut_level.clone())
             .map(|level_name| {
                 Cut {
-                    level_name: level_name,
+                    level_name,
                     members: members.clone().into_iter().map(|s| s.into()).collect(),
                 }
             })
@@ -211,13 +236,21 @@ impl Cut {
                 ))
             })?)
     }
+
+    pub fn level_name(&self) -> &LevelName {
+        &self.level_name
+    }
+
+    pub fn members(&self) -> &[String] {
+        &self.members
+    }
 }
 
 impl fmt::Display for Cut {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         // members must be more than 0, checked by assert on serialization
         if self.members.len() == 1 {
-            write!(f, "{}.&[{}]", self.level_name, self.members[0])
+            write!(f, "{}.&[{}]", self.level_name, escape(&self.members[0]))
         } else {
             let mut out = String::new();
             out.push('{');
@@ -226,13 +259,13 @@ impl fmt::Display for Cut {
             out.push_str(
                 format!(
                     "{}.&[{}]",
-                    self.level_name, members.next().unwrap()
+                    self.level_name, escape(members.next().unwrap())
                 ).as_str()
             );
 
             for member in members {
-                out.push_str(",");
-                out.push_str(format!("{}.&[{}]", self.level_name, member).as_str());
+                out.push(',');
+                out.push_str(format!("{}.&[{}]", self.level_name, escape(member)).as_str());
             }
             out.push('}');
 
@@ -241,45 +274,15 @@ impl fmt::Display for Cut {
     }
 }
 
-// TODO I should use a parser for this, just splitting is kind of
-// a pain
 impl FromStr for Cut {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let name_vec: Vec<_> = if s.chars().nth(0).unwrap() == '[' {
-            // check if starts with '[', then assume
-            // that this means that it's a qualified name
-            // with [] wrappers. This means that can't just
-            // split on any periods, only periods that fall
-            // outside the []
-            let pattern: &[_] = &['[', ']'];
-
-            // TODO maybe this should be split into
-            // left and right trims
-            //
-            // This is to deal with case where
-            // & is inserted before member, which
-            // "].[" won't match
-            let s = s.trim_matches(pattern);
-            s.split("].")
-                .map(|s| s.trim_left_matches('['))
-                .collect()
-        } else {
-            s.split(".")
-                .collect()
-        };
-
-        let members: Vec<_> = name_vec[name_vec.len()-1]
-            .trim_left_matches('&')
-            .trim_left_matches('[')
-            .split(',')
-            .map(|s| s.trim_left_matches('&').to_owned())
-            .collect();
+        let (level_name, members) = parse::parse_cut(s)?;
 
         Ok(Cut {
-            level_name: LevelName::from_vec(name_vec[0..name_vec.len()-1].to_vec())?,
-            members: members,
+            level_name: LevelName::from_vec(level_name)?,
+            members,
         })
     }
 }
@@ -310,7 +313,7 @@ impl Property {
         Ok(LevelName::from_vec(property[0..property.len()-1].to_vec())
             .map(|level_name| {
                 Property {
-                    level_name: level_name,
+                    level_name,
                     property: property[property.len()-1].clone().into(),
                 }
             })
@@ -328,38 +331,31 @@ impl Property {
     pub fn drill_level(&self) -> Drilldown {
         Drilldown(self.level_name.clone())
     }
+
+    pub fn level_name(&self) -> &LevelName {
+        &self.level_name
+    }
+
+    pub fn property(&self) -> &str {
+        self.property.as_str()
+    }
 }
 
 impl fmt::Display for Property {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}.[{}]", self.level_name, self.property)
+        write!(f, "{}.[{}]", self.level_name, escape(&self.property))
     }
 }
 
 impl FromStr for Property {
     type Err = Error;
 
-    /// NAIVE IMPL, does not deal with escaped brackets,
-    /// unpaired brackets, etc. Just checks whether the
-    /// first char is a bracket to determine whether to
-    /// parse using brackets or not.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let name_vec: Vec<_> = (if s.chars().nth(0).unwrap() == '[' {
-            // check if starts with '[', then assume
-            // that this means that it's a qualified name
-            // with [] wrappers. This means that can't just
-            // split on any periods, only periods that fall
-            // outside the []
-            let pattern: &[_] = &['[', ']'];
-            let s = s.trim_matches(pattern);
-            s.split("].[")
-        } else {
-            s.split(".")
-        }).collect();
+        let (level_name, property) = parse::parse_property(s)?;
 
         Ok(Property {
-            level_name: LevelName::from_vec(name_vec[0..name_vec.len()-1].to_vec())?,
-            property: name_vec[name_vec.len()-1].to_owned(),
+            level_name: LevelName::from_vec(level_name)?,
+            property,
         })
     }
 }
@@ -477,7 +473,8 @@ mod test {
 
         // test cut2
         let cut2_test_1 = "Geography.Geography.County.1,2".parse::<Cut>().unwrap();
-        let cut2_test_2 = "[Geography].[Geography].[County].&[1,2]".parse::<Cut>().unwrap();
+        // commas inside brackets are part of the member key
+        let cut2_test_2 = "[Geography].[Geography].[County].&[1],&[2]".parse::<Cut>().unwrap();
         let cut2_test_3 = "Geography.County.1,2".parse::<Cut>().unwrap();
         let cut2_test_4 = "Geography.County.&1,2".parse::<Cut>().unwrap();
         let cut2_test_5 = "Geography.County.&1,&2".parse::<Cut>().unwrap();
@@ -488,6 +485,19 @@ mod test {
         assert_eq!(cut2, cut2_test_4);
         assert_eq!(cut2, cut2_test_5);
 
+        // test cut2 round trip through Display set syntax
+        let cut2_test_6 = cut2.to_string().parse::<Cut>().unwrap();
+
+        assert_eq!(cut2, cut2_test_6);
+
+        // test member keys with commas, periods, and brackets
+        let cut3 = Cut::new("Geography", "Geography", "County", vec!["Smith, John", "1.5", "a]b"]);
+        let cut3_test_1 = "Geography.County.&[Smith, John],&[1.5],&[a]]b]".parse::<Cut>().unwrap();
+        let cut3_test_2 = cut3.to_string().parse::<Cut>().unwrap();
+
+        assert_eq!(cut3, cut3_test_1);
+        assert_eq!(cut3, cut3_test_2);
+
         // test property
         let property_test_1 = "Geography.Geography.County.name_en".parse::<Property>().unwrap();
         let property_test_2 = "[Geography].[Geography].[County].[name_en]".parse::<Property>().unwrap();
@@ -496,5 +506,45 @@ mod test {
         assert_eq!(property, property_test_1);
         assert_eq!(property, property_test_2);
         assert_eq!(property, property_test_3);
+
+        // test measure
+        let measure = Measure::new("Avg. Price");
+
+        assert_eq!(measure, "Avg. Price".parse::<Measure>().unwrap());
+        assert_eq!(measure, "[Avg. Price]".parse::<Measure>().unwrap());
+        assert_eq!(measure, "[Measures].[Avg. Price]".parse::<Measure>().unwrap());
+    }
+
+    #[test]
+    fn test_parse_names_with_periods() {
+        let level = LevelName::new("Date", "Fiscal.Year", "Fiscal Quarter");
+        let property = Property::new("Date", "Fiscal.Year", "Fiscal Quarter", "name.en");
+
+        assert_eq!(level, "Date.[Fiscal.Year].Fiscal Quarter".parse::<LevelName>().unwrap());
+        assert_eq!(level, level.to_string().parse::<LevelName>().unwrap());
+        assert_eq!(property, property.to_string().parse::<Property>().unwrap());
+    }
+
+    #[test]
+    fn test_display_escapes() {
+        let level = LevelName::new("Sales ]2016]", "Sales ]2016]", "Level");
+        let property = Property::new("Geography", "Geography", "County", "name]");
+
+        assert_eq!(level.to_string(), "[Sales ]]2016]]].[Sales ]]2016]]].[Level]");
+        assert_eq!(property.to_string(), "[Geography].[Geography].[County].[name]]]");
+        assert_eq!(level, level.to_string().parse::<LevelName>().unwrap());
+        assert_eq!(property, property.to_string().parse::<Property>().unwrap());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("".parse::<LevelName>().is_err());
+        assert!("".parse::<Drilldown>().is_err());
+        assert!("".parse::<Measure>().is_err());
+        assert!("".parse::<Cut>().is_err());
+        assert!("".parse::<Property>().is_err());
+
+        let err = "[Geography].[County".parse::<Drilldown>().unwrap_err();
+        assert_eq!(err.to_string(), "unclosed '[' at column 13\n  [Geography].[County\n              ^");
     }
 }
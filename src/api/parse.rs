@@ -0,0 +1,736 @@
+//! Tokenizer and parser for qualified names, following
+//! MDX identifier quoting rules:
+//! - a name is a '.' delimited list of identifiers
+//! - an identifier is either bracketed, e.g. [Dollars Sum],
+//!   or plain, e.g. Geography
+//! - inside brackets any char is allowed, and ']' is escaped
+//!   by doubling it, e.g. [Sales ]]2016]]]
+//! - plain identifiers can't contain '.', ',', '[', ']', '{' or '}'
+//! - member keys may be prefixed with '&', e.g. &[1] or &1
+//!
+//! For cuts, the last part may be a ',' delimited list of
+//! members (cli convenience, not mdx), or the whole cut may be
+//! a set of member names in braces, as generated by Display:
+//! {[Year].[Year].[Year].&[2015],[Year].[Year].[Year].&[2016]}
+
+use failure::Fail;
+use std::fmt;
+
+/// Error with the column (1-based, in chars) where parsing failed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    input: String,
+    column: usize,
+    msg: String,
+}
+
+impl ParseError {
+    fn new<S: Into<String>>(input: &str, column: usize, msg: S) -> Self {
+        ParseError {
+            input: input.to_owned(),
+            column,
+            msg: msg.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at column {}\n  {}\n  {}^",
+            self.msg,
+            self.column,
+            self.input,
+            " ".repeat(self.column - 1),
+        )
+    }
+}
+
+impl Fail for ParseError {}
+
+/// Escapes a name for use inside brackets
+pub(crate) fn escape(s: &str) -> String {
+    s.replace(']', "]]")
+}
+
+/// Brackets a name only if it can't be parsed as a plain identifier
+pub(crate) fn quote(s: &str) -> String {
+    let needs_brackets = s.is_empty() ||
+        s.trim() != s ||
+        s.starts_with('&') ||
+        s.chars().any(|c| ".,[]{}".contains(c));
+
+    if needs_brackets {
+        format!("[{}]", escape(s))
+    } else {
+        s.to_owned()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Dot,
+    Comma,
+    Amp,
+    LBrace,
+    RBrace,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::Ident(ref s) => write!(f, "name {:?}", s),
+            Token::Dot => write!(f, "'.'"),
+            Token::Comma => write!(f, "','"),
+            Token::Amp => write!(f, "'&'"),
+            Token::LBrace => write!(f, "'{{'"),
+            Token::RBrace => write!(f, "'}}'"),
+        }
+    }
+}
+
+/// Token with its 1-based column
+#[derive(Debug, Clone, PartialEq)]
+struct Spanned {
+    token: Token,
+    column: usize,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Spanned>, ParseError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let column = i + 1;
+        let token = match chars[i] {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            },
+            '.' => { i += 1; Token::Dot },
+            ',' => { i += 1; Token::Comma },
+            '&' => { i += 1; Token::Amp },
+            '{' => { i += 1; Token::LBrace },
+            '}' => { i += 1; Token::RBrace },
+            ']' => {
+                return Err(ParseError::new(input, column, "unmatched ']'"));
+            },
+            '[' => {
+                // bracketed identifier; "]]" is an escaped ']'
+                let mut name = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some(&']') if chars.get(i + 1) == Some(&']') => {
+                            name.push(']');
+                            i += 2;
+                        },
+                        Some(&']') => {
+                            i += 1;
+                            break;
+                        },
+                        Some(&c) => {
+                            name.push(c);
+                            i += 1;
+                        },
+                        None => {
+                            return Err(ParseError::new(input, column, "unclosed '['"));
+                        },
+                    }
+                }
+                if name.is_empty() {
+                    return Err(ParseError::new(input, column, "empty name '[]'"));
+                }
+                Token::Ident(name)
+            },
+            _ => {
+                let mut name = String::new();
+                while let Some(&c) = chars.get(i) {
+                    if ".,[]{}".contains(c) {
+                        break;
+                    }
+                    name.push(c);
+                    i += 1;
+                }
+                Token::Ident(name.trim_end().to_owned())
+            },
+        };
+
+        tokens.push(Spanned { token, column });
+    }
+
+    Ok(tokens)
+}
+
+/// One identifier in a name
+#[derive(Debug, Clone, PartialEq)]
+struct Ident {
+    name: String,
+    key: bool,
+    column: usize,
+}
+
+/// One '.' delimited part of a name. Only the
+/// members of a cut can have more than one ident.
+#[derive(Debug, Clone, PartialEq)]
+struct Part {
+    idents: Vec<Ident>,
+}
+
+impl Part {
+    fn column(&self) -> usize {
+        self.idents[0].column
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<Spanned>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Result<Self, ParseError> {
+        let tokens = tokenize(input)?;
+        if tokens.is_empty() {
+            return Err(ParseError::new(input, 1, "empty name"));
+        }
+
+        Ok(Parser {
+            input,
+            tokens,
+            pos: 0,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|spanned| &spanned.token)
+    }
+
+    /// column of the next token, or one past the end of input
+    fn column(&self) -> usize {
+        self.tokens.get(self.pos)
+            .map(|spanned| spanned.column)
+            .unwrap_or_else(|| self.input.chars().count() + 1)
+    }
+
+    fn error<S: Into<String>>(&self, column: usize, msg: S) -> ParseError {
+        ParseError::new(self.input, column, msg)
+    }
+
+    fn unexpected(&self, expected: &str) -> ParseError {
+        match self.peek() {
+            Some(token) => self.error(self.column(), format!("expected {}, found {}", expected, token)),
+            None => self.error(self.column(), format!("expected {}, found end of input", expected)),
+        }
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_end(&self) -> Result<(), ParseError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(token) => Err(self.error(self.column(), format!("unexpected {}", token))),
+        }
+    }
+
+    fn ident(&mut self) -> Result<Ident, ParseError> {
+        let column = self.column();
+        let key = self.eat(&Token::Amp);
+
+        let name = match self.peek() {
+            Some(Token::Ident(name)) => name.clone(),
+            _ => return Err(self.unexpected("name")),
+        };
+        self.pos += 1;
+
+        Ok(Ident { name, key, column })
+    }
+
+    /// One part; a ',' delimited list of idents if allowed
+    fn part(&mut self, allow_list: bool) -> Result<Part, ParseError> {
+        let mut idents = vec![self.ident()?];
+
+        while allow_list && self.eat(&Token::Comma) {
+            idents.push(self.ident()?);
+        }
+
+        Ok(Part { idents })
+    }
+
+    /// '.' delimited parts
+    fn parts(&mut self, allow_list: bool) -> Result<Vec<Part>, ParseError> {
+        let mut parts = vec![self.part(allow_list)?];
+
+        while self.eat(&Token::Dot) {
+            parts.push(self.part(allow_list)?);
+        }
+
+        Ok(parts)
+    }
+}
+
+/// Names of parts which must be single, plain (not key) idents
+fn plain_names(parser: &Parser, parts: &[Part]) -> Result<Vec<String>, ParseError> {
+    parts.iter()
+        .map(|part| {
+            if part.idents.len() > 1 {
+                return Err(parser.error(
+                    part.idents[1].column,
+                    "unexpected list; only the members of a cut can be a list (bracket names containing ',')",
+                ));
+            }
+            let ident = &part.idents[0];
+            if ident.key {
+                return Err(parser.error(
+                    ident.column,
+                    "unexpected '&'; only the members of a cut can be keys",
+                ));
+            }
+            Ok(ident.name.clone())
+        })
+        .collect()
+}
+
+/// Level names are Dimension.Level or Dimension.Hierarchy.Level
+fn check_level_len(parser: &Parser, parts: &[Part], after: &str) -> Result<(), ParseError> {
+    if parts.len() == 2 || parts.len() == 3 {
+        Ok(())
+    } else {
+        Err(parser.error(
+            parts.first().map(|part| part.column()).unwrap_or(1),
+            format!(
+                "expected Dimension.Level or Dimension.Hierarchy.Level{}, found {} part(s)",
+                after,
+                parts.len() + if after.is_empty() { 0 } else { 1 },
+            ),
+        ))
+    }
+}
+
+/// Returns 2 or 3 names: [dimension, (hierarchy,) level]
+pub(crate) fn parse_level_name(input: &str) -> Result<Vec<String>, ParseError> {
+    let mut parser = Parser::new(input)?;
+    let parts = parser.parts(false)?;
+    parser.expect_end()?;
+
+    check_level_len(&parser, &parts, "")?;
+    plain_names(&parser, &parts)
+}
+
+/// Returns level names and property name
+pub(crate) fn parse_property(input: &str) -> Result<(Vec<String>, String), ParseError> {
+    let mut parser = Parser::new(input)?;
+    let parts = parser.parts(false)?;
+    parser.expect_end()?;
+
+    let (property, level) = parts.split_last().unwrap();
+    check_level_len(&parser, level, ".Property")?;
+
+    let mut names = plain_names(&parser, level)?;
+    names.extend(plain_names(&parser, std::slice::from_ref(property))?);
+    let property = names.pop().unwrap();
+
+    Ok((names, property))
+}
+
+/// Returns level names and member keys
+pub(crate) fn parse_cut(input: &str) -> Result<(Vec<String>, Vec<String>), ParseError> {
+    let mut parser = Parser::new(input)?;
+
+    if parser.eat(&Token::LBrace) {
+        // set of full member names
+        let first_column = parser.column();
+        let parts = parser.parts(false)?;
+        let (level, mut members) = cut_parts(&parser, parts)?;
+
+        while parser.eat(&Token::Comma) {
+            let column = parser.column();
+            let parts = parser.parts(false)?;
+            let (other_level, other_members) = cut_parts(&parser, parts)?;
+            if other_level != level {
+                return Err(parser.error(
+                    column,
+                    format!(
+                        "cut set members must all be on the level of the first member (column {})",
+                        first_column,
+                    ),
+                ));
+            }
+            members.extend(other_members);
+        }
+
+        if !parser.eat(&Token::RBrace) {
+            return Err(parser.unexpected("',' or '}'"));
+        }
+        parser.expect_end()?;
+
+        Ok((level, members))
+    } else {
+        let parts = parser.parts(true)?;
+        parser.expect_end()?;
+
+        cut_parts(&parser, parts)
+    }
+}
+
+fn cut_parts(parser: &Parser, parts: Vec<Part>) -> Result<(Vec<String>, Vec<String>), ParseError> {
+    let (members, level) = parts.split_last().unwrap();
+    check_level_len(parser, level, ".Members")?;
+
+    let level = plain_names(parser, level)?;
+    let members = members.idents.iter().map(|ident| ident.name.clone()).collect();
+
+    Ok((level, members))
+}
+
+/// Measures are usually unbracketed, and their names may contain
+/// periods, so an unbracketed measure is taken as is. A bracketed
+/// measure may be prefixed with [Measures].
+pub(crate) fn parse_measure(input: &str) -> Result<String, ParseError> {
+    let trimmed = input.trim();
+    if !trimmed.starts_with('[') {
+        if trimmed.is_empty() {
+            return Err(ParseError::new(input, 1, "empty name"));
+        }
+        return Ok(trimmed.to_owned());
+    }
+
+    let mut parser = Parser::new(input)?;
+    let parts = parser.parts(false)?;
+    parser.expect_end()?;
+
+    let mut names = plain_names(&parser, &parts)?;
+    match names.len() {
+        1 => Ok(names.pop().unwrap()),
+        2 if names[0] == "Measures" => Ok(names.pop().unwrap()),
+        _ => Err(parser.error(
+            parts[0].column(),
+            format!("expected Measure or [Measures].[Measure], found {} parts", names.len()),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn level(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn err_at<T: fmt::Debug>(res: Result<T, ParseError>) -> (usize, String) {
+        let err = res.unwrap_err();
+        (err.column, err.msg)
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let tokens: Vec<Token> = tokenize("[Geo]].x].Level.&[1], &2 {}")
+            .unwrap()
+            .into_iter()
+            .map(|spanned| spanned.token)
+            .collect();
+
+        assert_eq!(tokens, vec![
+            Token::Ident("Geo].x".to_owned()),
+            Token::Dot,
+            Token::Ident("Level".to_owned()),
+            Token::Dot,
+            Token::Amp,
+            Token::Ident("1".to_owned()),
+            Token::Comma,
+            Token::Amp,
+            Token::Ident("2".to_owned()),
+            Token::LBrace,
+            Token::RBrace,
+        ]);
+    }
+
+    #[test]
+    fn test_tokenize_columns() {
+        let columns: Vec<usize> = tokenize("ab.[c d].é,&x")
+            .unwrap()
+            .into_iter()
+            .map(|spanned| spanned.column)
+            .collect();
+
+        assert_eq!(columns, vec![1, 3, 4, 9, 10, 11, 12, 13]);
+    }
+
+    #[test]
+    fn test_level_name_plain() {
+        assert_eq!(parse_level_name("Geography.County").unwrap(), level(&["Geography", "County"]));
+        assert_eq!(
+            parse_level_name("Geography.Geography.County").unwrap(),
+            level(&["Geography", "Geography", "County"])
+        );
+        assert_eq!(
+            parse_level_name(" Geography . County ").unwrap(),
+            level(&["Geography", "County"])
+        );
+        assert_eq!(
+            parse_level_name("Port of Entry.Port Name").unwrap(),
+            level(&["Port of Entry", "Port Name"])
+        );
+        assert_eq!(parse_level_name("R&D.Lab").unwrap(), level(&["R&D", "Lab"]));
+    }
+
+    #[test]
+    fn test_level_name_bracketed() {
+        assert_eq!(
+            parse_level_name("[Geography].[Geography].[County]").unwrap(),
+            level(&["Geography", "Geography", "County"])
+        );
+        assert_eq!(
+            parse_level_name("[Geography].[County]").unwrap(),
+            level(&["Geography", "County"])
+        );
+        assert_eq!(
+            parse_level_name("[Date.Fiscal].[Fiscal.Year]").unwrap(),
+            level(&["Date.Fiscal", "Fiscal.Year"])
+        );
+        assert_eq!(
+            parse_level_name("[Size, Large].[Bin]").unwrap(),
+            level(&["Size, Large", "Bin"])
+        );
+        assert_eq!(
+            parse_level_name("[Sales ]]2016]]].[a]]]").unwrap(),
+            level(&["Sales ]2016]", "a]"])
+        );
+        assert_eq!(
+            parse_level_name("[ Spaced ].[Level]").unwrap(),
+            level(&[" Spaced ", "Level"])
+        );
+    }
+
+    #[test]
+    fn test_level_name_mixed() {
+        assert_eq!(
+            parse_level_name("Geography.[County]").unwrap(),
+            level(&["Geography", "County"])
+        );
+        assert_eq!(
+            parse_level_name("[Year].Fiscal Year.[Fiscal.Year]").unwrap(),
+            level(&["Year", "Fiscal Year", "Fiscal.Year"])
+        );
+    }
+
+    #[test]
+    fn test_level_name_errors() {
+        assert_eq!(err_at(parse_level_name("")), (1, "empty name".to_owned()));
+        assert_eq!(err_at(parse_level_name("   ")), (1, "empty name".to_owned()));
+        assert_eq!(err_at(parse_level_name("[Geography].[County")), (13, "unclosed '['".to_owned()));
+        assert_eq!(err_at(parse_level_name("Geography].County")), (10, "unmatched ']'".to_owned()));
+        assert_eq!(err_at(parse_level_name("[].County")), (1, "empty name '[]'".to_owned()));
+        assert_eq!(
+            err_at(parse_level_name("Geography..County")),
+            (11, "expected name, found '.'".to_owned())
+        );
+        assert_eq!(
+            err_at(parse_level_name("Geography.County.")),
+            (18, "expected name, found end of input".to_owned())
+        );
+        assert_eq!(
+            err_at(parse_level_name(".County")),
+            (1, "expected name, found '.'".to_owned())
+        );
+        assert_eq!(
+            err_at(parse_level_name("[Geography][County]")),
+            (12, "unexpected name \"County\"".to_owned())
+        );
+        assert_eq!(
+            err_at(parse_level_name("Geography.County,State")),
+            (17, "unexpected ','".to_owned())
+        );
+        assert_eq!(
+            err_at(parse_level_name("Geography.&County")),
+            (11, "unexpected '&'; only the members of a cut can be keys".to_owned())
+        );
+        assert_eq!(
+            err_at(parse_level_name("County")),
+            (1, "expected Dimension.Level or Dimension.Hierarchy.Level, found 1 part(s)".to_owned())
+        );
+        assert_eq!(
+            err_at(parse_level_name("A.B.C.D")),
+            (1, "expected Dimension.Level or Dimension.Hierarchy.Level, found 4 part(s)".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_error_display() {
+        let err = parse_level_name("[Geography].[County").unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "unclosed '[' at column 13\n  [Geography].[County\n              ^"
+        );
+    }
+
+    #[test]
+    fn test_property() {
+        assert_eq!(
+            parse_property("Geography.County.name_en").unwrap(),
+            (level(&["Geography", "County"]), "name_en".to_owned())
+        );
+        assert_eq!(
+            parse_property("[Geography].[Geography].[County].[name.en]").unwrap(),
+            (level(&["Geography", "Geography", "County"]), "name.en".to_owned())
+        );
+        assert_eq!(
+            parse_property("Geography.[County].[Name, English]").unwrap(),
+            (level(&["Geography", "County"]), "Name, English".to_owned())
+        );
+
+        assert_eq!(
+            err_at(parse_property("Geography.County")),
+            (1, "expected Dimension.Level or Dimension.Hierarchy.Level.Property, found 2 part(s)".to_owned())
+        );
+        assert_eq!(
+            err_at(parse_property("Geography.County.&name_en")),
+            (18, "unexpected '&'; only the members of a cut can be keys".to_owned())
+        );
+        assert_eq!(err_at(parse_property("")), (1, "empty name".to_owned()));
+    }
+
+    #[test]
+    fn test_cut_list() {
+        let geo = level(&["Geography", "County"]);
+
+        assert_eq!(parse_cut("Geography.County.1").unwrap(), (geo.clone(), level(&["1"])));
+        assert_eq!(parse_cut("Geography.County.&1").unwrap(), (geo.clone(), level(&["1"])));
+        assert_eq!(parse_cut("Geography.County.1,2").unwrap(), (geo.clone(), level(&["1", "2"])));
+        assert_eq!(parse_cut("Geography.County.&1,2").unwrap(), (geo.clone(), level(&["1", "2"])));
+        assert_eq!(parse_cut("Geography.County.&1, &2").unwrap(), (geo.clone(), level(&["1", "2"])));
+        assert_eq!(parse_cut("[Geography].[County].&[1]").unwrap(), (geo.clone(), level(&["1"])));
+        assert_eq!(
+            parse_cut("[Geography].[County].&[1],&[2]").unwrap(),
+            (geo.clone(), level(&["1", "2"]))
+        );
+        assert_eq!(
+            parse_cut("[Geography].[County].[1],[2]").unwrap(),
+            (geo.clone(), level(&["1", "2"]))
+        );
+    }
+
+    #[test]
+    fn test_cut_bracketed_keys() {
+        let geo = level(&["Geography", "County"]);
+
+        // commas and periods inside brackets are part of the key
+        assert_eq!(
+            parse_cut("Geography.County.&[Smith, John]").unwrap(),
+            (geo.clone(), level(&["Smith, John"]))
+        );
+        assert_eq!(
+            parse_cut("Geography.County.&[1.5],&[2.5]").unwrap(),
+            (geo.clone(), level(&["1.5", "2.5"]))
+        );
+        assert_eq!(
+            parse_cut("Geography.County.&[a]]b]").unwrap(),
+            (geo.clone(), level(&["a]b"]))
+        );
+    }
+
+    #[test]
+    fn test_cut_set() {
+        let year = level(&["Year", "Year", "Year"]);
+
+        assert_eq!(
+            parse_cut("{[Year].[Year].[Year].&[2015],[Year].[Year].[Year].&[2016]}").unwrap(),
+            (year.clone(), level(&["2015", "2016"]))
+        );
+        assert_eq!(
+            parse_cut("{ Year.Year.Year.2015 }").unwrap(),
+            (year.clone(), level(&["2015"]))
+        );
+
+        assert_eq!(
+            err_at(parse_cut("{[Year].[Year].&[2015],[Geo].[State].&[1]}")),
+            (24, "cut set members must all be on the level of the first member (column 2)".to_owned())
+        );
+        assert_eq!(
+            err_at(parse_cut("{Year.Year.2015")),
+            (16, "expected ',' or '}', found end of input".to_owned())
+        );
+        assert_eq!(
+            err_at(parse_cut("{Year.Year.2015}.x")),
+            (17, "unexpected '.'".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_cut_errors() {
+        assert_eq!(err_at(parse_cut("")), (1, "empty name".to_owned()));
+        assert_eq!(
+            err_at(parse_cut("Geography.County")),
+            (1, "expected Dimension.Level or Dimension.Hierarchy.Level.Members, found 2 part(s)".to_owned())
+        );
+        assert_eq!(
+            err_at(parse_cut("Geography.County.1,")),
+            (20, "expected name, found end of input".to_owned())
+        );
+        assert_eq!(
+            err_at(parse_cut("Geography.County,State.1")),
+            (18, "unexpected list; only the members of a cut can be a list (bracket names containing ',')".to_owned())
+        );
+        assert_eq!(
+            err_at(parse_cut("Geography.&County.1")),
+            (11, "unexpected '&'; only the members of a cut can be keys".to_owned())
+        );
+        assert_eq!(
+            err_at(parse_cut("Geography.County.&")),
+            (19, "expected name, found end of input".to_owned())
+        );
+        assert_eq!(
+            err_at(parse_cut("Geography.County.&[1")),
+            (19, "unclosed '['".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_measure() {
+        assert_eq!(parse_measure("Dollars Sum").unwrap(), "Dollars Sum");
+        assert_eq!(parse_measure(" Avg. Price ").unwrap(), "Avg. Price");
+        assert_eq!(parse_measure("[Dollars Sum]").unwrap(), "Dollars Sum");
+        assert_eq!(parse_measure("[Measures].[Dollars Sum]").unwrap(), "Dollars Sum");
+        assert_eq!(parse_measure("[Avg. Price]").unwrap(), "Avg. Price");
+        assert_eq!(parse_measure("[Price [USD]]]").unwrap(), "Price [USD]");
+
+        assert_eq!(err_at(parse_measure("")), (1, "empty name".to_owned()));
+        assert_eq!(err_at(parse_measure("[Dollars Sum")), (1, "unclosed '['".to_owned()));
+        assert_eq!(
+            err_at(parse_measure("[Geography].[Dollars Sum]")),
+            (1, "expected Measure or [Measures].[Measure], found 2 parts".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("Geography"), "Geography");
+        assert_eq!(quote("Port of Entry"), "Port of Entry");
+        assert_eq!(quote("Fiscal.Year"), "[Fiscal.Year]");
+        assert_eq!(quote("a]b"), "[a]]b]");
+        assert_eq!(quote("&1"), "[&1]");
+        assert_eq!(quote(" x"), "[ x]");
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("Sales ]2016]"), "Sales ]]2016]]");
+        assert_eq!(
+            parse_level_name(&format!("[{}].[{}]", escape("a]b"), escape("]"))).unwrap(),
+            level(&["a]b", "]"])
+        );
+    }
+}
@@ -0,0 +1,321 @@
+//! Running the queries of a query file (see spec module) and
+//! writing each result to its own file, e.g. for nightly extracts.
+//!
+//! Each query needs an `output` path; `format` defaults to json.
+//! A failing query doesn't stop the run, and its output file is
+//! left as it was.
+
+use failure::Error;
+use serde_json;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use mondrian_rest::aggregate::Aggregation;
+use mondrian_rest::api::ResponseFormat;
+use mondrian_rest::error::CliError;
+use mondrian_rest::schema::CubeDescription;
+use mondrian_rest::Client;
+use spec::QuerySpec;
+use testing::format_error;
+use testing::pool::run_parallel;
+
+#[derive(Debug, Default)]
+pub struct BatchOptions {
+    /// Number of queries to run at once
+    pub jobs: usize,
+    /// Check names against the cube schema before querying
+    pub validate: bool,
+    pub verbose: bool,
+}
+
+#[derive(Debug)]
+pub enum Outcome {
+    /// Output path and size in bytes
+    Written(PathBuf, usize),
+    Failed(String),
+}
+
+#[derive(Debug)]
+pub struct BatchResult {
+    pub name: String,
+    pub outcome: Outcome,
+    pub duration: Duration,
+}
+
+/// Every query needs its own output path, so that one result
+/// can't overwrite another. Paths are compared after
+/// normalizing, so e.g. `out/a.csv` and `./out/a.csv` are the same.
+pub fn check_outputs(queries: &[QuerySpec]) -> Result<(), Error> {
+    let mut outputs = Vec::new();
+
+    for query in queries {
+        let output = query.output.as_ref()
+            .ok_or_else(|| CliError::Config(format!("Query {:?} has no output path", query.name)))?;
+        let normalized = normalize(output);
+        if outputs.contains(&normalized) {
+            return Err(CliError::Config(format!(
+                "Query {:?} has the same output path as another query: {:?}",
+                query.name, output,
+            )).into());
+        }
+        outputs.push(normalized);
+    }
+
+    Ok(())
+}
+
+/// Absolute path with `.` and `..` resolved, without going to the
+/// filesystem, since outputs needn't exist yet
+fn normalize(path: &Path) -> PathBuf {
+    let path = match env::current_dir() {
+        Ok(dir) => dir.join(path),
+        Err(_) => path.to_path_buf(),
+    };
+
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => { normalized.pop(); },
+            component => normalized.push(component.as_os_str()),
+        }
+    }
+    normalized
+}
+
+/// Runs the queries, up to `options.jobs` at once. Results are
+/// in the order of the queries.
+pub fn run(client: &Client, queries: &[QuerySpec], options: &BatchOptions) -> Vec<BatchResult> {
+    // each cube's schema is fetched once, before running queries
+    let mut cubes: HashMap<&str, Result<CubeDescription, String>> = HashMap::new();
+    if options.validate {
+        for query in queries {
+            cubes.entry(query.cube.as_str())
+                .or_insert_with(|| client.cube(query.cube.as_str()).map_err(|err| format_error(&err)));
+        }
+    }
+
+    run_parallel(queries, options.jobs, |query| {
+        let start = Instant::now();
+
+        let cube = match cubes.get(query.cube.as_str()) {
+            Some(Ok(cube)) => Some(cube),
+            Some(Err(msg)) => {
+                return BatchResult {
+                    name: query.name.clone(),
+                    outcome: Outcome::Failed(format!("Could not get schema of cube {:?}:\n{}", query.cube, msg)),
+                    duration: start.elapsed(),
+                };
+            },
+            None => None,
+        };
+
+        let outcome = match run_one(client, query, cube, options.verbose) {
+            Ok((path, size)) => Outcome::Written(path, size),
+            Err(err) => Outcome::Failed(format_error(&err)),
+        };
+
+        BatchResult {
+            name: query.name.clone(),
+            outcome,
+            duration: start.elapsed(),
+        }
+    })
+}
+
+fn run_one(client: &Client,
+    query: &QuerySpec,
+    cube: Option<&CubeDescription>,
+    verbose: bool,
+    ) -> Result<(PathBuf, usize), Error>
+{
+    let req = query.query_builder(client.base_url())?;
+    if let Some(cube) = cube {
+        req.validate(cube)?;
+    }
+
+    if verbose {
+        println!("{}", req.url()?);
+    }
+    let resp = client.aggregate(&req)?;
+
+    let out = if *req.response_format() == ResponseFormat::Table {
+        let agg: Aggregation = serde_json::from_str(&resp)?;
+        agg.to_string()
+    } else {
+        resp
+    };
+
+    // checked by check_outputs
+    let path = query.output.clone().unwrap();
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)
+            .map_err(|err| format_err!("Could not create output dir {:?}: {}", dir, err))?;
+    }
+    fs::write(&path, &out)
+        .map_err(|err| format_err!("Could not write output {:?}: {}", path, err))?;
+
+    Ok((path, out.len()))
+}
+
+pub struct Summary<'a> {
+    pub results: &'a [BatchResult],
+}
+
+impl<'a> Summary<'a> {
+    pub fn failed(&self) -> usize {
+        self.results.iter()
+            .filter(|res| matches!(res.outcome, Outcome::Failed(_)))
+            .count()
+    }
+}
+
+impl<'a> fmt::Display for Summary<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for res in self.results {
+            let secs = res.duration.as_secs_f64();
+            match res.outcome {
+                Outcome::Written(ref path, size) => {
+                    writeln!(f, "ok       {} -> {} ({} bytes, {:.3}s)", res.name, path.display(), size, secs)?;
+                },
+                Outcome::Failed(ref msg) => {
+                    writeln!(f, "FAILED   {} ({:.3}s)", res.name, secs)?;
+                    for line in msg.lines() {
+                        writeln!(f, "    {}", line)?;
+                    }
+                },
+            }
+        }
+
+        let failed = self.failed();
+        write!(f, "\nBatch: {} queries, {} written, {} failed", self.results.len(), self.results.len() - failed, failed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::Path;
+
+    use mondrian_rest::test_support::{fixture_dir, replay, EXPORTS_JSON};
+
+    fn query(name: &str, measure: &str, format: Option<&str>, output: Option<&Path>) -> QuerySpec {
+        QuerySpec {
+            name: name.to_owned(),
+            cube: "exports".to_owned(),
+            drilldowns: vec!["Geography.State".to_owned()],
+            named_sets: Vec::new(),
+            measures: vec![measure.to_owned()],
+            cuts: Vec::new(),
+            properties: Vec::new(),
+            parents: false,
+            nonempty: false,
+            distinct: false,
+            sparse: false,
+            format: format.map(|format| format.to_owned()),
+            output: output.map(|output| output.to_path_buf()),
+        }
+    }
+
+    #[test]
+    fn test_check_outputs() {
+        let a = Path::new("out/a.csv");
+        assert!(check_outputs(&[query("a", "Count", None, Some(a))]).is_ok());
+        assert!(check_outputs(&[query("a", "Count", None, None)]).is_err());
+        assert!(check_outputs(&[query("a", "Count", None, Some(a)), query("b", "Count", None, Some(a))]).is_err());
+
+        // the same file, written differently
+        for &other in &["./out/a.csv", "out/./a.csv", "out/../out/a.csv"] {
+            let b = Path::new(other);
+            assert!(check_outputs(&[query("a", "Count", None, Some(a)), query("b", "Count", None, Some(b))]).is_err(), "{}", other);
+        }
+        let cwd = env::current_dir().unwrap().join("out/a.csv");
+        assert!(check_outputs(&[query("a", "Count", None, Some(a)), query("b", "Count", None, Some(&cwd))]).is_err());
+
+        let b = Path::new("out/b.csv");
+        assert!(check_outputs(&[query("a", "Count", None, Some(a)), query("b", "Count", None, Some(b))]).is_ok());
+    }
+
+    #[test]
+    fn test_batch_replay() {
+        let base_url = "http://localhost:5000";
+        let dir = fixture_dir("batch-out");
+
+        let table = query("table", "Count", Some("table"), Some(&dir.join("table.txt")));
+        let csv = query("csv", "Count", Some("csv"), Some(&dir.join("csv/count.csv")));
+        let typo = query("typo", "Cuont", None, Some(&dir.join("typo.json")));
+        let failing = query("failing", "Dollars Sum", Some("csv"), Some(&dir.join("failing.csv")));
+        let queries = vec![table, csv, typo, failing];
+
+        let url = |query: &QuerySpec| query.query_builder(base_url).unwrap().url().unwrap().to_string();
+        let responses = [
+            (format!("{}/cubes/exports/", base_url), 200, EXPORTS_JSON),
+            (url(&queries[0]), 200, r#"{
+                "axes": [
+                    { "members": [{ "name": "Count", "full_name": "[Measures].[Count]", "caption": "Count" }] },
+                    { "members": [{ "name": "Alaska", "full_name": "[Geography].[Alaska]", "caption": "Alaska" }] }
+                ],
+                "axis_dimensions": [
+                    { "name": "Measures", "caption": "Measures" },
+                    { "name": "Geography", "caption": "Geography" }
+                ],
+                "values": [[3]]
+            }"#),
+            (url(&queries[1]), 200, "State,Count\nAlaska,3\n"),
+            (url(&queries[3]), 500, r#"{"error": "Column 'dollars' not found"}"#),
+        ];
+        let responses: Vec<_> = responses.iter()
+            .map(|&(ref url, status, body)| (url.as_str(), status, body))
+            .collect();
+        let client = Client::with_transport(replay("batch", &responses), base_url);
+
+        let options = BatchOptions { jobs: 2, validate: true, verbose: false };
+        let results = run(&client, &queries, &options);
+
+        let names: Vec<_> = results.iter().map(|res| res.name.as_str()).collect();
+        assert_eq!(names, vec!["table", "csv", "typo", "failing"]);
+        assert_eq!(Summary { results: &results }.failed(), 2);
+
+        assert!(fs::read_to_string(dir.join("table.txt")).unwrap().contains("Alaska"));
+        assert_eq!(fs::read_to_string(dir.join("csv/count.csv")).unwrap(), "State,Count\nAlaska,3\n");
+        assert!(!dir.join("typo.json").exists());
+
+        match results[2].outcome {
+            Outcome::Failed(ref msg) => assert!(msg.contains("did you mean \"Count\"")),
+            ref outcome => panic!("expected a validation failure, got {:?}", outcome),
+        }
+        match results[3].outcome {
+            Outcome::Failed(ref msg) => assert!(msg.contains("Column 'dollars' not found")),
+            ref outcome => panic!("expected a mondrian error, got {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn test_batch_summary() {
+        let results = vec![
+            BatchResult {
+                name: "a".to_owned(),
+                outcome: Outcome::Written(PathBuf::from("out/a.csv"), 120),
+                duration: Duration::from_millis(250),
+            },
+            BatchResult {
+                name: "b".to_owned(),
+                outcome: Outcome::Failed("[500] Mondrian error".to_owned()),
+                duration: Duration::from_millis(1500),
+            },
+        ];
+
+        assert_eq!(
+            Summary { results: &results }.to_string(),
+            "ok       a -> out/a.csv (120 bytes, 0.250s)\n\
+            FAILED   b (1.500s)\n\
+            \x20   [500] Mondrian error\n\
+            \n\
+            Batch: 2 queries, 1 written, 1 failed"
+        );
+    }
+}
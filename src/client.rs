@@ -0,0 +1,159 @@
+//! Client for one mondrian-rest server.
+//!
+//! Owns the http client (through a `Transport`, so that responses
+//! can be recorded or replayed) and the base url of the server.
+//! Queries are built with `Client::query`, which starts a
+//! `QueryBuilder` on the same base url.
+//!
+//! Error responses are categorized (see error module), so a
+//! mondrian runtime error can be told from e.g. a proxy error.
+
+use failure::Error;
+use reqwest::{self, Url};
+use serde_json;
+
+use api::{self, LevelName, QueryBuilder};
+use schema::{CubeDescription, CubeDescriptions, Members};
+use transport::{Mode, Transport};
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    transport: Transport,
+    base_url: String,
+}
+
+impl Client {
+    /// Live client, with reqwest's default settings
+    pub fn new<S: Into<String>>(base_url: S) -> Self {
+        Client::with_transport(Transport::new(reqwest::Client::new(), Mode::Live), base_url)
+    }
+
+    pub fn with_transport<S: Into<String>>(transport: Transport, base_url: S) -> Self {
+        Client {
+            transport,
+            base_url: base_url.into(),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Starts a query on this server
+    pub fn query(&self) -> QueryBuilder {
+        api::query(self.base_url.clone())
+    }
+
+    /// Descriptions of all cubes
+    pub fn cubes(&self) -> Result<CubeDescriptions, Error> {
+        let url = self.query().url()?;
+        Ok(serde_json::from_str(&self.get(url)?)?)
+    }
+
+    pub fn cube<S: Into<String>>(&self, cube_name: S) -> Result<CubeDescription, Error> {
+        let url = self.query().cube(cube_name).url()?;
+        Ok(serde_json::from_str(&self.get(url)?)?)
+    }
+
+    pub fn members<S: Into<String>>(&self, cube_name: S, level_name: &LevelName) -> Result<Members, Error> {
+        let mut req = self.query();
+        req.cube(cube_name)
+            .members(level_name.clone());
+
+        Ok(serde_json::from_str(&self.get(req.url()?)?)?)
+    }
+
+    /// Runs the query and returns the unparsed body, in the
+    /// query's response format (json can be parsed to an
+    /// `aggregate::Aggregation`). The query goes to its own base
+    /// url, which is this server's if it was started with `query`.
+    pub fn aggregate(&self, query: &QueryBuilder) -> Result<String, Error> {
+        self.get(query.url()?)
+    }
+
+    /// Asks the server to flush schema and cache and reset
+    pub fn flush<S: Into<String>>(&self, secret: S) -> Result<(), Error> {
+        let mut base_url = self.base_url.clone();
+        api::add_trailing_slash(&mut base_url);
+
+        let mut url = Url::parse(&base_url)?.join("flush")?;
+        url.query_pairs_mut().append_pair("secret", &secret.into());
+
+        self.get(url)?;
+        Ok(())
+    }
+
+    /// Execute the call and return
+    /// the body as unparsed string
+    pub fn get(&self, url: Url) -> Result<String, Error> {
+        let resp = self.transport.get(url)?;
+
+        if !resp.status.is_success() {
+            return Err(api::server_error(resp.status, resp.body).into());
+        }
+
+        Ok(resp.body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use error::{exit_code, EXIT_MONDRIAN, EXIT_SERVER};
+    use test_support::{replay, EXPORTS_JSON};
+
+    #[test]
+    fn test_get_replay() {
+        let url = "http://localhost:5000/cubes/exports/aggregate.json?drilldown%5B%5D=County";
+        let transport = replay("client-get", &[
+            ("http://localhost:5000/cubes", 200, r#"{"cubes": []}"#),
+            (url, 500, r#"{"error": "Column 'county' not found"}"#),
+        ]);
+        let client = Client::with_transport(transport, "http://localhost:5000");
+
+        let cubes = client.get(Url::parse("http://localhost:5000/cubes").unwrap()).unwrap();
+        assert_eq!(cubes, r#"{"cubes": []}"#);
+
+        let err = client.get(Url::parse(url).unwrap()).unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_MONDRIAN);
+    }
+
+    #[test]
+    fn test_flush_replay() {
+        let transport = replay("flush-ok", &[("http://localhost:5000/flush?secret=x", 200, "")]);
+        let client = Client::with_transport(transport, "http://localhost:5000");
+        assert!(client.flush("secret123").is_ok());
+
+        let transport = replay("flush-forbidden", &[("http://localhost:5000/flush?secret=x", 403, "Forbidden")]);
+        let client = Client::with_transport(transport, "http://localhost:5000");
+        let err = client.flush("wrong").unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_SERVER);
+    }
+
+    #[test]
+    fn test_describe_replay() {
+        let cubes = format!(r#"{{ "cubes": [{}] }}"#, EXPORTS_JSON);
+        let members = r#"{
+            "name": "State",
+            "caption": "State",
+            "members": [{
+                "name": "Alaska", "full_name": "[Geography].[Alaska]", "caption": "Alaska",
+                "key": 2, "all_member?": false, "drillable?": true, "depth": 2,
+                "num_children": 0, "parent_name": "All Geographies", "level_name": "State", "children": []
+            }]
+        }"#;
+        let transport = replay("client-describe", &[
+            ("http://localhost:5000/cubes/", 200, &cubes),
+            ("http://localhost:5000/cubes/exports/", 200, EXPORTS_JSON),
+            ("http://localhost:5000/cubes/exports/dimensions/Geography/hierarchies/Geography/levels/State/members", 200, members),
+        ]);
+        let client = Client::with_transport(transport, "http://localhost:5000");
+
+        assert_eq!(client.cubes().unwrap().cubes[0].name, "exports");
+        assert_eq!(client.cube("exports").unwrap().name, "exports");
+
+        let level_name = "Geography.State".parse().unwrap();
+        let members = client.members("exports", &level_name).unwrap();
+        assert_eq!(members.sample_keys().len(), 1);
+    }
+}
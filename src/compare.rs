@@ -0,0 +1,355 @@
+//! Comparing the results of the same aggregate query on two
+//! servers, e.g. before and after migrating the database
+//! behind mondrian.
+//!
+//! Rows are matched by the full names of their drilldown members,
+//! and values by measure name, so the order of rows and measures
+//! in the two responses doesn't matter. Rows with the same label
+//! can't be matched, so they're reported as duplicates, and only
+//! the first of them is compared.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use mondrian_rest::aggregate::{self, Aggregation, Row};
+
+/// Values are equal if their difference is within either the
+/// absolute tolerance, or the relative tolerance (a fraction of
+/// the larger value). Both default to zero, i.e. exact equality.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tolerance {
+    pub absolute: f64,
+    pub relative: f64,
+}
+
+impl Tolerance {
+    pub fn equal(&self, left: Option<f64>, right: Option<f64>) -> bool {
+        match (left, right) {
+            (Some(l), Some(r)) => {
+                if l.is_nan() || r.is_nan() {
+                    return l.is_nan() && r.is_nan();
+                }
+                // also covers infinities
+                if l == r {
+                    return true;
+                }
+                let diff = (l - r).abs();
+                diff <= self.absolute || diff <= self.relative * l.abs().max(r.abs())
+            },
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ValueDiff {
+    pub row: String,
+    pub measure: String,
+    pub left: Option<f64>,
+    pub right: Option<f64>,
+}
+
+#[derive(Debug)]
+pub struct Comparison {
+    /// Number of rows found on both sides
+    pub rows: usize,
+    pub rows_only_left: Vec<String>,
+    pub rows_only_right: Vec<String>,
+    /// Labels of more than one row on the same side
+    pub duplicate_rows_left: Vec<String>,
+    pub duplicate_rows_right: Vec<String>,
+    pub measures_only_left: Vec<String>,
+    pub measures_only_right: Vec<String>,
+    pub diffs: Vec<ValueDiff>,
+    /// Names of the two sides in the output
+    pub labels: (&'static str, &'static str),
+}
+
+impl Comparison {
+    pub fn new(left: &Aggregation, right: &Aggregation, tolerance: &Tolerance) -> Self {
+        let left_measures = measure_names(left);
+        let right_measures = measure_names(right);
+
+        // index of each shared measure on both sides
+        let measures: Vec<(&str, usize, usize)> = left_measures.iter()
+            .enumerate()
+            .filter_map(|(l, name)| {
+                right_measures.iter()
+                    .position(|other| other == name)
+                    .map(|r| (name.as_str(), l, r))
+            })
+            .collect();
+
+        let (left_rows, duplicate_rows_left) = unique_rows(left.rows());
+        let (right_rows, duplicate_rows_right) = unique_rows(right.rows());
+
+        let right_index: HashMap<&str, &Row> = right_rows.iter()
+            .map(|(label, row)| (label.as_str(), row))
+            .collect();
+
+        let mut rows = 0;
+        let mut rows_only_left = Vec::new();
+        let mut diffs = Vec::new();
+
+        for (label, row) in &left_rows {
+            let other = match right_index.get(label.as_str()) {
+                Some(other) => other,
+                None => {
+                    rows_only_left.push(label.clone());
+                    continue;
+                },
+            };
+            rows += 1;
+
+            for &(measure, l, r) in &measures {
+                let left_value = row.values[l];
+                let right_value = other.values[r];
+                if !tolerance.equal(left_value, right_value) {
+                    diffs.push(ValueDiff {
+                        row: label.clone(),
+                        measure: measure.to_owned(),
+                        left: left_value,
+                        right: right_value,
+                    });
+                }
+            }
+        }
+
+        let left_labels: HashSet<&str> = left_rows.iter()
+            .map(|(label, _)| label.as_str())
+            .collect();
+        let rows_only_right = right_rows.iter()
+            .filter(|(label, _)| !left_labels.contains(label.as_str()))
+            .map(|(label, _)| label.clone())
+            .collect();
+
+        Comparison {
+            rows,
+            rows_only_left,
+            rows_only_right,
+            duplicate_rows_left,
+            duplicate_rows_right,
+            measures_only_left: missing(&left_measures, &right_measures),
+            measures_only_right: missing(&right_measures, &left_measures),
+            diffs,
+            labels: ("left", "right"),
+        }
+    }
+
+    pub fn is_match(&self) -> bool {
+        self.rows_only_left.is_empty() &&
+            self.rows_only_right.is_empty() &&
+            self.duplicate_rows_left.is_empty() &&
+            self.duplicate_rows_right.is_empty() &&
+            self.measures_only_left.is_empty() &&
+            self.measures_only_right.is_empty() &&
+            self.diffs.is_empty()
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (left, right) = self.labels;
+
+        for &(side, measures) in &[(left, &self.measures_only_left), (right, &self.measures_only_right)] {
+            if !measures.is_empty() {
+                writeln!(f, "Measures only in {}: {}\n", side, measures.join(", "))?;
+            }
+        }
+
+        for &(side, rows) in &[(left, &self.rows_only_left), (right, &self.rows_only_right)] {
+            if !rows.is_empty() {
+                writeln!(f, "Rows only in {} ({}):", side, rows.len())?;
+                for row in rows.iter() {
+                    writeln!(f, "  {}", row)?;
+                }
+                writeln!(f)?;
+            }
+        }
+
+        for &(side, rows) in &[(left, &self.duplicate_rows_left), (right, &self.duplicate_rows_right)] {
+            if !rows.is_empty() {
+                writeln!(f, "Duplicate rows in {} ({}), only the first is compared:", side, rows.len())?;
+                for row in rows.iter() {
+                    writeln!(f, "  {}", row)?;
+                }
+                writeln!(f)?;
+            }
+        }
+
+        if !self.diffs.is_empty() {
+            writeln!(f, "Differing values ({}):", self.diffs.len())?;
+            for diff in &self.diffs {
+                writeln!(f, "  {}  {}: {} -> {}",
+                    diff.row, diff.measure, show_value(diff.left), show_value(diff.right))?;
+            }
+            writeln!(f)?;
+        }
+
+        if self.is_match() {
+            write!(f, "Compared {} rows: no differences", self.rows)
+        } else {
+            write!(f, "Compared {} rows: {} only in {}, {} only in {}, {} values differing",
+                self.rows, self.rows_only_left.len(), left, self.rows_only_right.len(), right, self.diffs.len())?;
+            let duplicates = self.duplicate_rows_left.len() + self.duplicate_rows_right.len();
+            if duplicates > 0 {
+                write!(f, ", {} duplicate rows", duplicates)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn measure_names(agg: &Aggregation) -> Vec<String> {
+    agg.measures().iter().map(|mea| mea.name.clone()).collect()
+}
+
+fn missing(names: &[String], others: &[String]) -> Vec<String> {
+    names.iter()
+        .filter(|name| !others.contains(name))
+        .cloned()
+        .collect()
+}
+
+/// Labels each row, keeping the first row of each label. Labels
+/// of the other rows are returned as duplicates (once each).
+fn unique_rows(rows: Vec<Row>) -> (Vec<(String, Row)>, Vec<String>) {
+    let mut seen = HashSet::new();
+    let mut unique = Vec::with_capacity(rows.len());
+    let mut duplicates = Vec::new();
+
+    for row in rows {
+        let label = row_label(&row);
+        if seen.contains(&label) {
+            if !duplicates.contains(&label) {
+                duplicates.push(label);
+            }
+        } else {
+            seen.insert(label.clone());
+            unique.push((label, row));
+        }
+    }
+
+    (unique, duplicates)
+}
+
+/// Full names of the row's members; a query without
+/// drilldowns has a single row.
+fn row_label(row: &Row) -> String {
+    if row.members.is_empty() {
+        return "(total)".to_owned();
+    }
+    row.members.iter()
+        .map(|member| member.full_name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn show_value(value: Option<f64>) -> String {
+    match value {
+        Some(_) => aggregate::format_value(value),
+        None => "empty".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json;
+
+    fn aggregation(counties: &[&str], measures: &[&str], values: &str) -> Aggregation {
+        let member = |name: &str| format!(
+            r#"{{ "name": "{0}", "full_name": "[Geography].[{0}]", "caption": "{0}" }}"#, name
+        );
+        let json = format!(r#"{{
+            "axes": [
+                {{ "members": [{}] }},
+                {{ "members": [{}] }}
+            ],
+            "axis_dimensions": [
+                {{ "name": "Measures", "caption": "Measures" }},
+                {{ "name": "Geography", "caption": "Geography" }}
+            ],
+            "values": {}
+        }}"#,
+            measures.iter().map(|mea| member(mea)).collect::<Vec<_>>().join(","),
+            counties.iter().map(|county| member(county)).collect::<Vec<_>>().join(","),
+            values,
+        );
+
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn test_tolerance() {
+        let exact = Tolerance::default();
+        assert!(exact.equal(Some(1.0), Some(1.0)));
+        assert!(!exact.equal(Some(1.0), Some(1.000001)));
+        assert!(exact.equal(None, None));
+        assert!(!exact.equal(Some(0.0), None));
+        assert!(exact.equal(Some(f64::NAN), Some(f64::NAN)));
+
+        let absolute = Tolerance { absolute: 0.01, relative: 0.0 };
+        assert!(absolute.equal(Some(100.0), Some(100.005)));
+        assert!(!absolute.equal(Some(100.0), Some(100.02)));
+
+        let relative = Tolerance { absolute: 0.0, relative: 0.001 };
+        assert!(relative.equal(Some(100000.0), Some(100050.0)));
+        assert!(!relative.equal(Some(1.0), Some(1.01)));
+    }
+
+    #[test]
+    fn test_compare_same() {
+        let left = aggregation(&["Adams", "Brown"], &["Dollars Sum", "Count"], "[[1, 2], [3, 4]]");
+        // rows and measures in another order
+        let right = aggregation(&["Brown", "Adams"], &["Count", "Dollars Sum"], "[[4, 3], [2, 1]]");
+
+        let comparison = Comparison::new(&left, &right, &Tolerance::default());
+
+        assert!(comparison.is_match());
+        assert_eq!(comparison.to_string(), "Compared 2 rows: no differences");
+    }
+
+    #[test]
+    fn test_compare_differences() {
+        let left = aggregation(&["Adams", "Brown", "Clark"], &["Dollars Sum", "Count"], "[[1, 2], [3, 4], [5, 6]]");
+        let right = aggregation(&["Adams", "Brown", "Dodge"], &["Dollars Sum"], "[[1.5], [null], [7]]");
+
+        let comparison = Comparison::new(&left, &right, &Tolerance { absolute: 0.0, relative: 0.5 });
+
+        assert!(!comparison.is_match());
+        assert_eq!(comparison.to_string(),
+            "Measures only in left: Count\n\
+            \n\
+            Rows only in left (1):\n\
+            \x20 [Geography].[Clark]\n\
+            \n\
+            Rows only in right (1):\n\
+            \x20 [Geography].[Dodge]\n\
+            \n\
+            Differing values (1):\n\
+            \x20 [Geography].[Brown]  Dollars Sum: 3 -> empty\n\
+            \n\
+            Compared 2 rows: 1 only in left, 1 only in right, 1 values differing"
+        );
+    }
+
+    #[test]
+    fn test_compare_duplicate_rows() {
+        let left = aggregation(&["Adams", "Adams", "Brown"], &["Count"], "[[1], [2], [3]]");
+        let right = aggregation(&["Adams", "Brown"], &["Count"], "[[1], [3]]");
+
+        let comparison = Comparison::new(&left, &right, &Tolerance::default());
+
+        assert!(!comparison.is_match());
+        assert_eq!(comparison.duplicate_rows_left, vec!["[Geography].[Adams]"]);
+        assert!(comparison.duplicate_rows_right.is_empty());
+        assert_eq!(comparison.to_string(),
+            "Duplicate rows in left (1), only the first is compared:\n\
+            \x20 [Geography].[Adams]\n\
+            \n\
+            Compared 2 rows: 0 only in left, 0 only in right, 0 values differing, 1 duplicate rows"
+        );
+    }
+}
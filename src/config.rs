@@ -3,32 +3,99 @@
 ///cubes
 ///    - no arg: all cubes and dims info
 ///    - arg: cube name: cube info
+///    - option: include, exclude, skip-file (filter all cubes)
 ///
 ///test
 ///    - no arg: all cubes
 ///    - arg: cube name
+///    - option: include, exclude, skip-file (filter all cubes)
+///    - option: report (junit or json)
+///    - option: strategy (quick or thorough)
+///    - flags: no-bisect, bisect-all, sample-cuts
+///    - option: jobs, rate (concurrency and rate limit)
+///    - option: slow-threshold, flag: timings
+///
+///diff
+///    - args: two base urls or saved schema snapshots
+///
+///compare
+///    - args: two base urls
+///    - arg/options: same query as query subcommand
+///    - option: abs-tolerance, rel-tolerance
+///
+///snapshot
+///    - record: arg: query file; option: dir
+///    - verify: option: dir, abs-tolerance, rel-tolerance
+///
+///batch
+///    - arg: query file (with format and output per query)
+///    - option: jobs; flag: no-validate
+///
+///mock
+///    - arg: cube descriptions file
+///    - option: fixtures, port, secret
 ///
 ///flush
 ///    - arg/env var: key
 ///
+///shell
+///    - interactive query building
+///
 ///query
 ///    - arg: cube name
 ///    - option: drilldown
+///    - option: named set
 ///    - option: cut
 ///    - option: measure
 ///    - flags: parents, debug, etc
 ///    - option: output (json, jsonrecord, csv)
 ///    - option: debug (url, js)
+///    - option: from-url, or load (saved query)
+///    - option: save
 ///
 ///
 ///global option/env var: base url
+///global option: record, replay (saved server responses)
+///
+///# Config file
+///
+///Named profiles can be defined in
+///`~/.config/mondrian-rest-cli/config.toml` (or the file
+///at env var MON_CLI_CONFIG):
+///
+///```toml
+///[profiles.prod]
+///base_url = "http://10.100.10.10:5000"
+///secret = "secret123"
+///timeout = 60
+///format = "csv"
+///verbose = false
+///```
+///
+///A profile is selected with `--profile` or env var MON_CLI_PROFILE.
+///If neither is set, the profile named `default` is used if present.
+///
+///Precedence, highest first:
+///    - cli flags
+///    - env vars (MON_CLI_BASE_URL, MON_CLI_SECRET, MON_CLI_VERBOSE)
+///    - profile
+///    - built-in defaults
 ///
 
+use dirs;
 use failure::Error;
+use std::collections::HashMap;
 use std::env;
+use std::fs;
+use std::path::PathBuf;
 use structopt::StructOpt;
+use toml;
 
-use api::ResponseFormat;
+use mondrian_rest::api::ResponseFormat;
+use mondrian_rest::error::CliError;
+use mondrian_rest::transport;
+use testing::report::Report;
+use testing::Strategy;
 
 #[derive(StructOpt, Debug)]
 #[structopt(
@@ -56,13 +123,19 @@ pub struct Config {
     )]
     pub base_url: Option<String>,
 
+    #[structopt(
+        short="P",
+        long="profile",
+        help="Named profile from config file; or env var MON_CLI_PROFILE",
+    )]
+    pub profile: Option<String>,
+
     #[structopt(
         short="t",
         long="timeout",
-        default_value="30",
-        help="Timeout in seconds",
+        help="Timeout in seconds [default: 30]",
     )]
-    pub timeout: usize,
+    pub timeout: Option<usize>,
 
     #[structopt(
         short="v",
@@ -70,6 +143,26 @@ pub struct Config {
     )]
     pub verbose: bool,
 
+    #[structopt(
+        long="no-verbose",
+        conflicts_with="verbose",
+        help="Not verbose, even if set in the profile or env var MON_CLI_VERBOSE",
+    )]
+    pub no_verbose: bool,
+
+    #[structopt(
+        long="record",
+        help="Save every server response in this directory, for --replay",
+    )]
+    pub record: Option<String>,
+
+    #[structopt(
+        long="replay",
+        conflicts_with="record",
+        help="Answer requests from responses saved with --record, without the network",
+    )]
+    pub replay: Option<String>,
+
     #[structopt(subcommand)]
     pub cmd: Command,
 }
@@ -101,6 +194,24 @@ pub enum Command {
             help="raw output for description",
         )]
         raw: bool,
+
+        #[structopt(
+            long="include",
+            help="Only cubes matching glob, when no cube name is given. Takes multiple.",
+        )]
+        include: Vec<String>,
+
+        #[structopt(
+            long="exclude",
+            help="Skip cubes matching glob, when no cube name is given. Takes multiple.",
+        )]
+        exclude: Vec<String>,
+
+        #[structopt(
+            long="skip-file",
+            help="File of cube names to skip, one per line, when no cube name is given",
+        )]
+        skip_file: Option<String>,
     },
 
     #[structopt(
@@ -113,6 +224,79 @@ pub enum Command {
             help="Test specified cube; empty arg will test all cubes")
         ]
         cube_name: Option<String>,
+
+        #[structopt(
+            long="include",
+            help="Only cubes matching glob, when no cube name is given. Takes multiple.",
+        )]
+        include: Vec<String>,
+
+        #[structopt(
+            long="exclude",
+            help="Skip cubes matching glob, when no cube name is given. Takes multiple.",
+        )]
+        exclude: Vec<String>,
+
+        #[structopt(
+            long="skip-file",
+            help="File of cube names to skip, one per line, when no cube name is given",
+        )]
+        skip_file: Option<String>,
+
+        #[structopt(
+            long="report",
+            help="Write a report of each test query; junit=<path> or json=<path>. Takes multiple.",
+        )]
+        reports: Vec<Report>,
+
+        #[structopt(
+            long="strategy",
+            help="quick: first level of each dimension; thorough: every level of every hierarchy [default: quick]",
+        )]
+        strategy: Option<Strategy>,
+
+        #[structopt(
+            long="no-bisect",
+            help="Don't bisect the measures of a failing query to find which cause the error",
+        )]
+        no_bisect: bool,
+
+        #[structopt(
+            long="bisect-all",
+            help="Also bisect the properties of a failing query",
+        )]
+        bisect_all: bool,
+
+        #[structopt(
+            long="sample-cuts",
+            help="Also fetch members of each level, and query with a cut on the first and last member",
+        )]
+        sample_cuts: bool,
+
+        #[structopt(
+            short="j",
+            long="jobs",
+            help="Number of test queries to run at once [default: 1]",
+        )]
+        jobs: Option<usize>,
+
+        #[structopt(
+            long="rate",
+            help="Maximum requests per second, across all jobs",
+        )]
+        rate: Option<f64>,
+
+        #[structopt(
+            long="slow-threshold",
+            help="Flag queries taking longer than this many seconds, and print timings",
+        )]
+        slow_threshold: Option<f64>,
+
+        #[structopt(
+            long="timings",
+            help="Print a table of query durations and response sizes for each cube",
+        )]
+        timings: bool,
     },
 
     #[structopt(
@@ -127,6 +311,179 @@ pub enum Command {
         secret: Option<String>,
     },
 
+    #[structopt(
+        name="diff",
+        about="Shows schema differences between two servers or snapshots",
+    )]
+    Diff {
+        #[structopt(
+            help="Old schema: base url, or file saved from `describe --raw`")
+        ]
+        left: String,
+
+        #[structopt(
+            help="New schema: base url, or file saved from `describe --raw`")
+        ]
+        right: String,
+    },
+
+    #[structopt(
+        name="compare",
+        about="Runs the same query on two servers and compares the results",
+    )]
+    Compare {
+        #[structopt(help="Base url of the first server")]
+        left: String,
+
+        #[structopt(help="Base url of the second server")]
+        right: String,
+
+        #[structopt(
+            help="Query specified cube",
+            required_unless="from_url")
+        ]
+        cube_name: Option<String>,
+
+        #[structopt(
+            long="from-url",
+            help="Start from an existing mondrian-rest aggregate url; its base url is replaced",
+        )]
+        from_url: Option<String>,
+
+        #[structopt(
+            short="d",
+            long="drilldown",
+            help="Fully qualified name '.' delimited. Takes multiple.",
+        )]
+        drilldowns: Vec<String>,
+
+        #[structopt(
+            long="named-set",
+            help="Named set to drill down on. Takes multiple.",
+        )]
+        named_sets: Vec<String>,
+
+        #[structopt(
+            short="m",
+            long="measure",
+            help="Fully qualified name '.' delimited. Takes multiple.",
+        )]
+        measures: Vec<String>,
+
+        #[structopt(
+            short="c",
+            long="cut",
+            help="Fully qualified name '.' delimited. Takes multiple.",
+        )]
+        cuts: Vec<String>,
+
+        #[structopt(
+            short="p",
+            long="property",
+            help="Fully qualified name '.' delimited. Takes multiple.",
+        )]
+        properties: Vec<String>,
+
+        #[structopt(
+            long="parents",
+        )]
+        parents: bool,
+
+        #[structopt(
+            long="nonempty",
+        )]
+        nonempty: bool,
+
+        #[structopt(
+            long="distinct",
+        )]
+        distinct: bool,
+
+        #[structopt(
+            long="sparse",
+        )]
+        sparse: bool,
+
+        #[structopt(
+            long="abs-tolerance",
+            help="Values within this absolute difference are equal [default: 0]",
+        )]
+        abs_tolerance: Option<f64>,
+
+        #[structopt(
+            long="rel-tolerance",
+            help="Values within this fraction of the larger value are equal, e.g. 0.001 [default: 0]",
+        )]
+        rel_tolerance: Option<f64>,
+    },
+
+    #[structopt(
+        name="batch",
+        about="Runs the queries of a query file, writing each result to its output file",
+    )]
+    Batch {
+        #[structopt(help="Toml file of queries, each with an output path")]
+        queries: String,
+
+        #[structopt(
+            short="j",
+            long="jobs",
+            help="Number of queries to run at once [default: 1]",
+        )]
+        jobs: Option<usize>,
+
+        #[structopt(
+            long="no-validate",
+            help="Don't check names against the cube schemas before querying",
+        )]
+        no_validate: bool,
+    },
+
+    #[structopt(
+        name="snapshot",
+        about="Records query results, and verifies them later",
+    )]
+    Snapshot {
+        #[structopt(subcommand)]
+        cmd: SnapshotCommand,
+    },
+
+    #[structopt(
+        name="mock",
+        about="Serves a fake mondrian-rest api on localhost",
+    )]
+    Mock {
+        #[structopt(
+            help="Cube descriptions, e.g. saved from `describe --raw`")
+        ]
+        cubes: String,
+
+        #[structopt(
+            long="fixtures",
+            help="Directory of member and aggregate responses, laid out like the api urls",
+        )]
+        fixtures: Option<String>,
+
+        #[structopt(
+            long="port",
+            help="Port to listen on [default: 5000]",
+        )]
+        port: Option<u16>,
+
+        #[structopt(
+            long="secret",
+            help="Secret required by /flush; any secret is accepted if not set",
+        )]
+        secret: Option<String>,
+    },
+
+    #[structopt(
+        name="shell",
+        alias="s",
+        about="Interactive shell for building queries, with tab completion",
+    )]
+    Shell,
+
     // TODO add options, and flush before query
     #[structopt(
         name="query",
@@ -134,10 +491,28 @@ pub enum Command {
         about="Runs a query on a cube",
     )]
     Query {
+        // Always Some after get_config, unless from_url or load is set
+        #[structopt(help="Query specified cube")]
+        cube_name: Option<String>,
+
         #[structopt(
-            help="Query specified cube")
-        ]
-        cube_name: String,
+            long="from-url",
+            help="Start from an existing mondrian-rest aggregate url; other options are added to it",
+        )]
+        from_url: Option<String>,
+
+        #[structopt(
+            long="load",
+            help="Start from a query saved with --save; other options are added to it",
+            conflicts_with="from_url",
+        )]
+        load: Option<String>,
+
+        #[structopt(
+            long="save",
+            help="Save the query to a file before running it; toml if it ends in .toml, else json",
+        )]
+        save: Option<String>,
 
         #[structopt(
             short="d",
@@ -146,6 +521,12 @@ pub enum Command {
         )]
         drilldowns: Vec<String>,
 
+        #[structopt(
+            long="named-set",
+            help="Named set to drill down on. Takes multiple.",
+        )]
+        named_sets: Vec<String>,
+
         #[structopt(
             short="m",
             long="measure",
@@ -192,47 +573,416 @@ pub enum Command {
         )]
         sparse: bool,
 
+        #[structopt(
+            long="no-validate",
+            help="Skip checking names against the cube schema before sending",
+        )]
+        no_validate: bool,
+
+        // Replaces the format of a query from a url or file; for
+        // any other query, resolved into `ResolvedConfig::format`
         #[structopt(
             short="f",
             long="format",
-            help="json, jsonrecords, or csv",
-            default_value="json",
+            help="json, jsonrecords, csv, or table [default: json]",
+        )]
+        format: Option<ResponseFormat>,
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub enum SnapshotCommand {
+    #[structopt(
+        name="record",
+        about="Runs the queries in a query file and saves their results",
+    )]
+    Record {
+        #[structopt(help="Toml file of queries")]
+        queries: String,
+
+        #[structopt(
+            long="dir",
+            help="Snapshot directory [default: snapshots]",
+        )]
+        dir: Option<String>,
+    },
+
+    #[structopt(
+        name="verify",
+        about="Re-runs saved queries and shows changed results",
+    )]
+    Verify {
+        #[structopt(
+            long="dir",
+            help="Snapshot directory [default: snapshots]",
+        )]
+        dir: Option<String>,
+
+        #[structopt(
+            long="abs-tolerance",
+            help="Values within this absolute difference are equal [default: 0]",
+        )]
+        abs_tolerance: Option<f64>,
+
+        #[structopt(
+            long="rel-tolerance",
+            help="Values within this fraction of the larger value are equal, e.g. 0.001 [default: 0]",
         )]
-        format: ResponseFormat,
+        rel_tolerance: Option<f64>,
+    },
+}
+
+impl Config {
+    pub fn transport_mode(&self) -> transport::Mode {
+        match (&self.record, &self.replay) {
+            (Some(dir), _) => transport::Mode::Record(PathBuf::from(dir)),
+            (None, Some(dir)) => transport::Mode::Replay(PathBuf::from(dir)),
+            (None, None) => transport::Mode::Live,
+        }
+    }
+}
+
+/// Settings after filling in the cli flags from env vars, the
+/// profile, and defaults.
+#[derive(Debug)]
+pub struct ResolvedConfig {
+    /// None only where a server isn't needed, or the query
+    /// (from a url or a saved file) carries its own
+    pub base_url: Option<String>,
+    /// In seconds
+    pub timeout: usize,
+    pub verbose: bool,
+    /// Format of a query built from the cli
+    pub format: ResponseFormat,
+    pub transport_mode: transport::Mode,
+    pub cmd: Command,
+}
+
+/// Settings for one named server in the config file.
+/// Every setting is optional, and is overridden by
+/// cli flags and env vars.
+#[derive(Debug, Default, Deserialize)]
+pub struct Profile {
+    pub base_url: Option<String>,
+    pub secret: Option<String>,
+    pub timeout: Option<usize>,
+    pub format: Option<String>,
+    pub verbose: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    profiles: HashMap<String, Profile>,
+}
+
+const DEFAULT_PROFILE: &str = "default";
+const DEFAULT_TIMEOUT: usize = 30;
+
+fn config_err(msg: String) -> Error {
+    CliError::Config(msg).into()
+}
+
+fn parse_bool_var(name: &str, value: &str) -> Result<bool, Error> {
+    match value {
+        "1" | "true" => Ok(true),
+        "0" | "false" | "" => Ok(false),
+        _ => Err(config_err(format!("Env var {} must be 1, true, 0, or false; got {:?}", name, value))),
+    }
+}
+
+fn config_file_path() -> Option<PathBuf> {
+    if let Ok(path) = env::var("MON_CLI_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+
+    dirs::home_dir().map(|home| {
+        home.join(".config")
+            .join("mondrian-rest-cli")
+            .join("config.toml")
+    })
+}
+
+/// Env vars read by `get_config`
+#[derive(Debug, Default)]
+struct EnvVars {
+    base_url: Option<String>,
+    secret: Option<String>,
+    verbose: Option<String>,
+}
+
+impl EnvVars {
+    fn read() -> Self {
+        EnvVars {
+            base_url: env::var("MON_CLI_BASE_URL").ok(),
+            secret: env::var("MON_CLI_SECRET").ok(),
+            verbose: env::var("MON_CLI_VERBOSE").ok(),
+        }
     }
 }
 
-pub fn get_config() -> Result<Config, Error> {
-    let mut config = Config::from_args();
+/// Looks up the selected profile in the config file at `path`.
+///
+/// If a profile is explicitly requested, the config file and
+/// the profile must both exist. Otherwise, the `default`
+/// profile is used if it exists.
+fn get_profile(path: Option<PathBuf>, requested: Option<String>) -> Result<Profile, Error> {
+    let config_file = match path {
+        Some(ref path) if path.exists() => {
+            let contents = fs::read_to_string(path)
+                .map_err(|err| CliError::Config(format!("Could not read config file {:?}: {}", path, err)))?;
+            toml::from_str::<ConfigFile>(&contents)
+                .map_err(|err| CliError::Config(format!("Could not parse config file {:?}: {}", path, err)))?
+        },
+        _ => ConfigFile::default(),
+    };
+
+    let mut profiles = config_file.profiles;
+
+    if let Some(name) = requested {
+        match profiles.remove(&name) {
+            Some(profile) => Ok(profile),
+            None => match path {
+                Some(path) => Err(config_err(format!("Profile {:?} not found in config file {:?}", name, path))),
+                None => Err(config_err(format!("Profile {:?} requested, but no config file found", name))),
+            },
+        }
+    } else {
+        Ok(profiles.remove(DEFAULT_PROFILE).unwrap_or_default())
+    }
+}
+
+pub fn get_config() -> Result<ResolvedConfig, Error> {
+    let config = Config::from_args();
+
+    let profile_name = config.profile.clone()
+        .or_else(|| env::var("MON_CLI_PROFILE").ok());
+    let profile = get_profile(config_file_path(), profile_name)?;
+
+    merge(config, EnvVars::read(), profile)
+}
+
+/// Fills in settings not given as cli flags from env vars, then
+/// the profile, then defaults, and checks required settings.
+fn merge(mut config: Config, env_vars: EnvVars, profile: Profile) -> Result<ResolvedConfig, Error> {
+    // a query from a url (or a saved query) carries its own
+    // base url, cube, and format
+    let from_url = match config.cmd {
+        Command::Query{ref from_url, ref load, ..} => from_url.is_some() || load.is_some(),
+        _ => false,
+    };
+
+    // diff and compare take both base urls (or snapshots) as args,
+    // and mock is the server
+    let no_base_url = matches!(config.cmd, Command::Diff{..} | Command::Compare{..} | Command::Mock{..});
+
     // check base url presence
     // TODO parse to url path?
     if config.base_url.is_none() {
-        if let Ok(base_url) = env::var("MON_CLI_BASE_URL") {
+        if let Some(base_url) = env_vars.base_url {
             config.base_url = Some(base_url);
-        } else {
-            bail!("Base url must be supplied");
+        } else if let Some(base_url) = profile.base_url {
+            config.base_url = Some(base_url);
+        } else if !from_url && !no_base_url {
+            return Err(config_err("Base url must be supplied".to_owned()));
         }
     }
 
+    let timeout = config.timeout
+        .or(profile.timeout)
+        .unwrap_or(DEFAULT_TIMEOUT);
+
+    // -v or --no-verbose, then the env var, then the profile
+    if !config.verbose && !config.no_verbose {
+        config.verbose = match env_vars.verbose {
+            Some(ref verbose) => parse_bool_var("MON_CLI_VERBOSE", verbose)?,
+            None => profile.verbose.unwrap_or(false),
+        };
+    }
+
     // check secret presence
     if let Command::Flush{ref mut secret, ..} = config.cmd {
         if secret.is_none() {
-            if let Ok(s) = env::var("MON_CLI_SECRET") {
+            if let Some(s) = env_vars.secret {
                 *secret = Some(s);
+            } else if let Some(ref s) = profile.secret {
+                *secret = Some(s.clone());
             } else {
-                bail!("Secret must be supplied");
+                return Err(config_err("Secret must be supplied".to_owned()));
             }
         }
     }
 
+    if let Command::Query{ref cube_name, ..} = config.cmd {
+        if cube_name.is_none() && !from_url {
+            return Err(config_err("Cube name must be supplied".to_owned()));
+        }
+    }
+
+    let cli_format = match config.cmd {
+        Command::Query{ref format, ..} => format.clone(),
+        _ => None,
+    };
+    let format = match (cli_format, profile.format) {
+        (Some(format), _) => format,
+        (None, Some(ref format)) => format.parse()?,
+        (None, None) => ResponseFormat::Json,
+    };
+
     // see if this error check can be pushed to structopt
     // check that query has query has at least one drilldown
-    // and at least one measure
-    if let Command::Query{ref drilldowns, ref measures, ..} = config.cmd {
-        if drilldowns.is_empty() || measures.is_empty() {
-            bail!("Dimension and measure must be supplied");
+    // (or named set) and at least one measure
+    // (same for compare)
+    let query_names = match config.cmd {
+        Command::Query{ref drilldowns, ref named_sets, ref measures, ..} => {
+            Some((drilldowns, named_sets, measures, from_url))
+        },
+        Command::Compare{ref drilldowns, ref named_sets, ref measures, ref from_url, ..} => {
+            Some((drilldowns, named_sets, measures, from_url.is_some()))
+        },
+        _ => None,
+    };
+    if let Some((drilldowns, named_sets, measures, from_url)) = query_names {
+        if !from_url && ((drilldowns.is_empty() && named_sets.is_empty()) || measures.is_empty()) {
+            return Err(config_err("Dimension and measure must be supplied".to_owned()));
+        }
+    }
+
+    Ok(ResolvedConfig {
+        transport_mode: config.transport_mode(),
+        base_url: config.base_url,
+        timeout,
+        verbose: config.verbose,
+        format,
+        cmd: config.cmd,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use error::{exit_code, EXIT_CONFIG};
+    use mondrian_rest::test_support::fixture_dir;
+
+    fn args(args: &[&str]) -> Config {
+        let mut argv = vec!["mondrian-rest-cli"];
+        argv.extend(args);
+        Config::from_clap(Config::clap().get_matches_from(argv))
+    }
+
+    fn profile() -> Profile {
+        Profile {
+            base_url: Some("http://profile".to_owned()),
+            secret: Some("profile-secret".to_owned()),
+            timeout: Some(60),
+            format: Some("csv".to_owned()),
+            verbose: Some(true),
         }
     }
 
-    Ok(config)
+    fn env_vars() -> EnvVars {
+        EnvVars {
+            base_url: Some("http://env".to_owned()),
+            secret: Some("env-secret".to_owned()),
+            verbose: Some("0".to_owned()),
+        }
+    }
+
+    fn secret(config: &ResolvedConfig) -> Option<&str> {
+        match config.cmd {
+            Command::Flush{ref secret} => secret.as_ref().map(|s| s.as_str()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_cli_over_env() {
+        let cli = args(&["-b", "http://cli", "-t", "5", "-v", "flush", "cli-secret"]);
+        let config = merge(cli, env_vars(), profile()).unwrap();
+
+        assert_eq!(config.base_url.as_ref().unwrap(), "http://cli");
+        assert_eq!(secret(&config), Some("cli-secret"));
+        assert_eq!(config.timeout, 5);
+        assert!(config.verbose);
+
+        let cli = args(&["--no-verbose", "flush"]);
+        let env_vars = EnvVars { verbose: Some("1".to_owned()), ..env_vars() };
+        assert!(!merge(cli, env_vars, profile()).unwrap().verbose);
+    }
+
+    #[test]
+    fn test_env_over_profile() {
+        let config = merge(args(&["flush"]), env_vars(), profile()).unwrap();
+
+        assert_eq!(config.base_url.as_ref().unwrap(), "http://env");
+        assert_eq!(secret(&config), Some("env-secret"));
+        // MON_CLI_VERBOSE=0 turns off the profile's verbose
+        assert!(!config.verbose);
+
+        let env_vars = EnvVars { verbose: Some("yes".to_owned()), ..EnvVars::default() };
+        let err = merge(args(&["flush"]), env_vars, profile()).unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_CONFIG);
+    }
+
+    #[test]
+    fn test_profile_over_default() {
+        let cli = args(&["query", "exports", "-d", "Year.Year", "-m", "Count"]);
+        let config = merge(cli, EnvVars::default(), profile()).unwrap();
+
+        assert_eq!(config.base_url.as_ref().unwrap(), "http://profile");
+        assert_eq!(config.timeout, 60);
+        assert_eq!(config.format, ResponseFormat::Csv);
+        assert!(config.verbose);
+
+        let config = merge(args(&["flush"]), EnvVars::default(), profile()).unwrap();
+        assert_eq!(secret(&config), Some("profile-secret"));
+    }
+
+    #[test]
+    fn test_defaults() {
+        let cli = args(&["-b", "http://cli", "query", "exports", "-d", "Year.Year", "-m", "Count"]);
+        let config = merge(cli, EnvVars::default(), Profile::default()).unwrap();
+
+        assert_eq!(config.timeout, DEFAULT_TIMEOUT);
+        assert_eq!(config.format, ResponseFormat::Json);
+        assert!(!config.verbose);
+
+        // nothing sets the base url or secret
+        let err = merge(args(&["describe"]), EnvVars::default(), Profile::default()).unwrap_err();
+        assert_eq!(err.to_string(), "Base url must be supplied");
+        let err = merge(args(&["-b", "http://cli", "flush"]), EnvVars::default(), Profile::default()).unwrap_err();
+        assert_eq!(err.to_string(), "Secret must be supplied");
+    }
+
+    #[test]
+    fn test_get_profile() {
+        let dir = fixture_dir("config");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(&path, r#"
+            [profiles.default]
+            base_url = "http://default"
+
+            [profiles.prod]
+            base_url = "http://prod"
+            timeout = 60
+        "#).unwrap();
+
+        let prod = get_profile(Some(path.clone()), Some("prod".to_owned())).unwrap();
+        assert_eq!(prod.base_url.unwrap(), "http://prod");
+        assert_eq!(prod.timeout, Some(60));
+
+        let default = get_profile(Some(path.clone()), None).unwrap();
+        assert_eq!(default.base_url.unwrap(), "http://default");
+
+        // a requested profile must exist, the default needn't
+        let err = get_profile(Some(path), Some("staging".to_owned())).unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_CONFIG);
+        assert!(err.to_string().contains("Profile \"staging\" not found"));
+
+        let missing = dir.join("missing.toml");
+        assert!(get_profile(Some(missing.clone()), None).unwrap().base_url.is_none());
+        assert!(get_profile(Some(missing), Some("prod".to_owned())).is_err());
+        assert!(get_profile(None, Some("prod".to_owned())).is_err());
+    }
 }
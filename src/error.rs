@@ -0,0 +1,128 @@
+//! Error categories, each with its own exit code,
+//! so that scripts can tell e.g. a schema bug from
+//! a network blip.
+//!
+//! Exit codes:
+//! - 1: other errors
+//! - 2: config
+//! - 3: name parse, or name not in schema
+//! - 4: http transport
+//! - 5: server responded 4xx/5xx
+//! - 6: mondrian runtime error
+//! - 7: one or more test or batch queries failed
+//! - 8: query results differ between servers, or from a snapshot
+
+use failure::{Error, Fail};
+use reqwest::{self, StatusCode};
+use std::fmt;
+use url;
+
+use api::parse::ParseError;
+
+pub const EXIT_OTHER: i32 = 1;
+pub const EXIT_CONFIG: i32 = 2;
+pub const EXIT_NAME: i32 = 3;
+pub const EXIT_TRANSPORT: i32 = 4;
+pub const EXIT_SERVER: i32 = 5;
+pub const EXIT_MONDRIAN: i32 = 6;
+pub const EXIT_TEST: i32 = 7;
+pub const EXIT_MISMATCH: i32 = 8;
+
+#[derive(Debug)]
+pub enum CliError {
+    /// Bad config file, missing settings, or invalid options
+    Config(String),
+    /// Name that can't be parsed, or isn't in the cube schema
+    Name(String),
+    /// Could not reach the server
+    Transport(String),
+    /// Server responded with an error that isn't from mondrian
+    Server {
+        status: StatusCode,
+        msg: String,
+    },
+    /// Mondrian runtime error, parsed from the error json
+    Mondrian {
+        status: StatusCode,
+        msg: String,
+    },
+    /// Test (or batch) run completed, but some queries failed
+    TestFailure(String),
+    /// Comparison (or snapshot verification) completed, but results differ
+    Mismatch(String),
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            CliError::Config(_) => EXIT_CONFIG,
+            CliError::Name(_) => EXIT_NAME,
+            CliError::Transport(_) => EXIT_TRANSPORT,
+            CliError::Server{..} => EXIT_SERVER,
+            CliError::Mondrian{..} => EXIT_MONDRIAN,
+            CliError::TestFailure(_) => EXIT_TEST,
+            CliError::Mismatch(_) => EXIT_MISMATCH,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CliError::Config(ref msg) => write!(f, "{}", msg),
+            CliError::Name(ref msg) => write!(f, "{}", msg),
+            CliError::Transport(ref msg) => write!(f, "Transport error: {}", msg),
+            CliError::Server{ref status, ref msg} => write!(f, "[{}]:\n{}", status, msg),
+            CliError::Mondrian{ref status, ref msg} => write!(f, "[{}] Mondrian error:\n{}", status, msg),
+            CliError::TestFailure(ref msg) => write!(f, "{}", msg),
+            CliError::Mismatch(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl Fail for CliError {}
+
+/// Finds the category of an error from anywhere in its
+/// chain of causes. Errors from the http client and the
+/// name parser are categorized even if they weren't
+/// wrapped in a CliError.
+pub fn exit_code(err: &Error) -> i32 {
+    for cause in err.causes() {
+        if let Some(err) = cause.downcast_ref::<CliError>() {
+            return err.exit_code();
+        }
+        if cause.downcast_ref::<ParseError>().is_some() {
+            return EXIT_NAME;
+        }
+        if cause.downcast_ref::<reqwest::Error>().is_some() {
+            return EXIT_TRANSPORT;
+        }
+        // only base urls are parsed, not server responses
+        if cause.downcast_ref::<url::ParseError>().is_some() {
+            return EXIT_CONFIG;
+        }
+    }
+
+    EXIT_OTHER
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_exit_code() {
+        let config: Error = CliError::Config("no base url".to_owned()).into();
+        let mondrian: Error = CliError::Mondrian {
+            status: StatusCode::InternalServerError,
+            msg: "bad column".to_owned(),
+        }.into();
+        let parse: Error = "[Geography".parse::<::api::Drilldown>().unwrap_err();
+        let other = format_err!("something else");
+
+        assert_eq!(exit_code(&config), EXIT_CONFIG);
+        assert_eq!(exit_code(&mondrian), EXIT_MONDRIAN);
+        assert_eq!(exit_code(&parse), EXIT_NAME);
+        assert_eq!(exit_code(&other), EXIT_OTHER);
+    }
+}
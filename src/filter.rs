@@ -0,0 +1,104 @@
+//! Filtering which cubes are covered when testing or
+//! describing all cubes.
+//!
+//! A cube is kept if it matches any include glob (or there are
+//! none), matches no exclude glob, and isn't in the skip file.
+//!
+//! The skip file has one cube name per line; blank lines and
+//! lines starting with '#' are ignored.
+
+use failure::Error;
+use glob::Pattern;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use mondrian_rest::error::CliError;
+
+#[derive(Debug, Default)]
+pub struct CubeFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    skip: HashSet<String>,
+}
+
+impl CubeFilter {
+    pub fn new(include: &[String], exclude: &[String], skip_file: Option<&Path>) -> Result<Self, Error> {
+        let skip = match skip_file {
+            Some(path) => {
+                let s = fs::read_to_string(path)
+                    .map_err(|err| CliError::Config(format!("Could not read skip file {:?}: {}", path, err)))?;
+                parse_skip_list(&s)
+            },
+            None => HashSet::new(),
+        };
+
+        Ok(CubeFilter {
+            include: parse_patterns(include)?,
+            exclude: parse_patterns(exclude)?,
+            skip,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty() && self.skip.is_empty()
+    }
+
+    pub fn matches(&self, cube_name: &str) -> bool {
+        let included = self.include.is_empty() ||
+            self.include.iter().any(|pattern| pattern.matches(cube_name));
+        let excluded = self.exclude.iter().any(|pattern| pattern.matches(cube_name));
+
+        included && !excluded && !self.skip.contains(cube_name)
+    }
+}
+
+fn parse_patterns(patterns: &[String]) -> Result<Vec<Pattern>, Error> {
+    patterns.iter()
+        .map(|pattern| {
+            Pattern::new(pattern)
+                .map_err(|err| CliError::Config(format!("Invalid cube pattern {:?}: {}", pattern, err)).into())
+        })
+        .collect()
+}
+
+fn parse_skip_list(s: &str) -> HashSet<String> {
+    s.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn strings(s: &[&str]) -> Vec<String> {
+        s.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut filter = CubeFilter::new(&strings(&["trade_*", "exports"]), &strings(&["*_legacy"]), None).unwrap();
+        filter.skip = parse_skip_list("# huge\ntrade_hs6\n\n  trade_broken  \n");
+
+        assert!(filter.matches("trade_hs4"));
+        assert!(filter.matches("exports"));
+        assert!(!filter.matches("imports"));
+        assert!(!filter.matches("trade_legacy"));
+        assert!(!filter.matches("trade_hs6"));
+        assert!(!filter.matches("trade_broken"));
+    }
+
+    #[test]
+    fn test_filter_empty() {
+        let filter = CubeFilter::new(&[], &strings(&["legacy_*"]), None).unwrap();
+
+        assert!(!filter.is_empty());
+        assert!(filter.matches("exports"));
+        assert!(!filter.matches("legacy_exports"));
+        assert!(CubeFilter::default().matches("anything"));
+        assert!(CubeFilter::new(&strings(&["[trade"]), &[], None).is_err());
+    }
+}
@@ -0,0 +1,33 @@
+//! Client library for the mondrian-rest api.
+//!
+//! - `api`: `QueryBuilder` and qualified names (levels, cuts, etc.),
+//!   for building mondrian-rest urls
+//! - `schema`: cube descriptions and members, for checking names
+//! - `aggregate`: aggregate responses, as rows or a table
+//! - `client`: `Client`, for sending requests to a server
+//! - `transport`: live requests, or recorded responses
+//! - `error`: error categories, with exit codes for the cli
+//! - `test_support`: fixtures for tests (not part of the api)
+//!
+//! The `mondrian-rest-cli` binary is built on this library.
+
+#[macro_use]
+extern crate failure;
+extern crate reqwest;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate strsim;
+extern crate url;
+
+pub mod aggregate;
+pub mod api;
+pub mod client;
+pub mod error;
+pub mod schema;
+pub mod transport;
+#[doc(hidden)]
+pub mod test_support;
+
+pub use client::Client;
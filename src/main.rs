@@ -4,49 +4,76 @@
 // x implement serde json to allow for parsing of cube descriptions for testing
 // x implement testing (flush, describe, query all dims of a cube)
 // x implement better error reporting (runtime Java error, and NaN for json, etc.)
+// x use parser for qualified names
+// x test checks responses and summarizes failures
 //
 // Future:
-// - use parser for Cut
 // - implement state machine for builder, to better control pattern. Now that
 //     members, flush, query, etc. are all possibilities.
 
+extern crate dirs;
 #[macro_use]
 extern crate failure;
+extern crate glob;
+extern crate mondrian_rest;
 extern crate reqwest;
+extern crate rustyline;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
 extern crate serde_json;
 extern crate structopt;
 #[macro_use]
 extern crate structopt_derive;
+extern crate toml;
+extern crate url;
 
-mod api;
+mod batch;
+mod compare;
 mod config;
-mod schema;
+mod filter;
+mod mock;
+mod shell;
+mod snapshot;
+mod spec;
+mod testing;
 
 use failure::Error;
-use reqwest::{Client, Url};
+use reqwest::Url;
+use std::convert::TryFrom;
+use std::fs;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 
-use config::Command;
-use api::names::{Drilldown, Measure, Property, LevelName};
-use schema::{CubeDescription, CubeDescriptions};
+use config::{Command, SnapshotCommand};
+use filter::CubeFilter;
+use mondrian_rest::{aggregate, api, error, schema, Client};
+use mondrian_rest::api::{LevelName, QueryBuilder};
+use mondrian_rest::error::CliError;
+use mondrian_rest::schema::{CubeDescription, CubeDescriptions};
+use mondrian_rest::transport::Transport;
 
 fn main() {
     if let Err(err) = run() {
         for cause in err.causes() {
-            println!("{}", cause);
+            eprintln!("{}", cause);
         }
+        process::exit(error::exit_code(&err));
     }
 }
 
 fn run() -> Result<(), Error> {
     let config = config::get_config()?;
 
-    let client = Client::builder()
+    let http_client = reqwest::Client::builder()
         .timeout(Duration::from_secs(config.timeout as u64))
         .build()?;
+    let transport = Transport::new(http_client, config.transport_mode);
 
     // TODO
     // Generate mondrianbuilder here
@@ -58,9 +85,15 @@ fn run() -> Result<(), Error> {
             cube_name,
             members,
             raw,
+            include,
+            exclude,
+            skip_file,
             } =>
         {
-            let mut req = api::query(config.base_url.unwrap());
+            let filter = cube_filter(&cube_name, &include, &exclude, &skip_file)?;
+
+            let client = Client::with_transport(transport, config.base_url.unwrap());
+            let mut req = client.query();
             if let Some(ref cube) = cube_name {
                 req.cube(cube.clone());
 
@@ -75,10 +108,23 @@ fn run() -> Result<(), Error> {
                 println!("{}", url);
             }
 
-            let resp = exec_query(&client, url)?;
+            let resp = client.get(url)?;
 
             if raw {
-                resp
+                if filter.is_empty() {
+                    resp
+                } else {
+                    let mut cubes: serde_json::Value = serde_json::from_str(&resp)?;
+                    if let Some(cubes) = cubes.get_mut("cubes").and_then(|cubes| cubes.as_array_mut()) {
+                        cubes.retain(|cube| {
+                            cube.get("name")
+                                .and_then(|name| name.as_str())
+                                .map(|name| filter.matches(name))
+                                .unwrap_or(true)
+                        });
+                    }
+                    serde_json::to_string(&cubes)?
+                }
             } else {
                 if let Some(cube) = cube_name {
                     let cube_or_members: String;
@@ -94,39 +140,231 @@ fn run() -> Result<(), Error> {
                     cube_or_members
                 } else  {
                     let cubes: CubeDescriptions = serde_json::from_str(&resp)?;
-                    cubes.cubes.iter().map(|cube| cube.to_string()).collect::<Vec<_>>().join("\n")
+                    cubes.cubes.iter()
+                        .filter(|cube| filter.matches(&cube.name))
+                        .map(|cube| cube.to_string())
+                        .collect::<Vec<_>>()
+                        .join("\n")
                 }
             }
         },
-        Command::Test {cube_name} => {
-            let mut req = api::query(config.base_url.clone().unwrap());
-
-            if let Some(cube) = cube_name {
-                req.cube(cube.clone());
-
-                let url = req.url()?;
-                let cube_description: schema::CubeDescription = serde_json::from_str(&exec_query(&client, url)?)?;
-                test_cube(&client, &cube_description, &config.base_url.unwrap(), &cube, config.verbose)?
+        Command::Test {cube_name, include, exclude, skip_file, reports, strategy, no_bisect, bisect_all, sample_cuts, jobs, rate, slow_threshold, timings} => {
+            if slow_threshold.map(|secs| !secs.is_finite() || secs < 0.0).unwrap_or(false) {
+                return Err(CliError::Config("Slow threshold must be a number of seconds".to_owned()).into());
+            }
+            let options = testing::TestOptions {
+                strategy: strategy.unwrap_or_default(),
+                bisect: !no_bisect,
+                bisect_all,
+                sample_cuts,
+                jobs: jobs.unwrap_or(1),
+                rate_limit: testing::pool::RateLimiter::new(rate),
+                slow_threshold: slow_threshold.map(Duration::from_secs_f64),
+                timings: timings || slow_threshold.is_some(),
+                verbose: config.verbose,
+            };
+            let filter = cube_filter(&cube_name, &include, &exclude, &skip_file)?;
+
+            let client = Client::with_transport(transport, config.base_url.unwrap());
+
+            let cube_descriptions = if let Some(cube) = cube_name {
+                vec![client.cube(cube)?]
             } else {
                 // for all cubes
-                let url = req.url()?;
-                let cube_descriptions: schema::CubeDescriptions = serde_json::from_str(&exec_query(&client, url)?)?;
-                for cube_description in cube_descriptions.cubes {
-                    test_cube(&client, &cube_description, config.base_url.as_ref().unwrap(), &cube_description.name, config.verbose)?
+                let mut cube_descriptions = client.cubes()?;
+                cube_descriptions.cubes.retain(|cube| filter.matches(&cube.name));
+                if cube_descriptions.cubes.is_empty() {
+                    return Err(CliError::Config("No cubes left to test after filtering".to_owned()).into());
+                }
+                cube_descriptions.cubes
+            };
+
+            let results = testing::test_cubes(&client, &cube_descriptions, &options);
+
+            for report in &reports {
+                report.write(&results)?;
+                if config.verbose {
+                    println!("Wrote report {:?}", report.path());
                 }
             }
-            "\nTest Complete".to_owned()
+
+            let summary = testing::Summary { results: &results };
+            println!("\n{}", summary);
+
+            let failed = summary.failed();
+            if failed > 0 {
+                return Err(CliError::TestFailure(
+                    format!("{} of {} test queries failed", failed, results.len())
+                ).into());
+            }
+            "Test Complete".to_owned()
+        },
+        Command::Diff {left, right} => {
+            let left = load_schema(&transport, &left, config.verbose)?;
+            let right = load_schema(&transport, &right, config.verbose)?;
+
+            let diff = schema::diff::SchemaDiff::new(&left, &right);
+            diff.to_string().trim_end().to_owned()
+        },
+        Command::Compare {
+            left,
+            right,
+            cube_name,
+            from_url,
+            drilldowns,
+            named_sets,
+            measures,
+            cuts,
+            properties,
+            parents,
+            nonempty,
+            distinct,
+            sparse,
+            abs_tolerance,
+            rel_tolerance,
+            } =>
+        {
+            let tolerance = tolerance(abs_tolerance, rel_tolerance)?;
+
+            // the base url is set for each server below
+            let mut req = match from_url {
+                Some(url) => {
+                    // named sets in the url are told from levels
+                    // by the cube schema of the left server
+                    let url = Url::parse(&url)?;
+                    let mut req = QueryBuilder::try_from(&url)?;
+                    let client = Client::with_transport(transport.clone(), left.clone());
+                    req.base_url(client.base_url());
+                    let description = cube_description(&client, &req, config.verbose)?;
+                    QueryBuilder::from_url_with_cube(&url, &description)?
+                },
+                None => QueryBuilder::default(),
+            };
+            if let Some(cube_name) = cube_name {
+                req.cube(cube_name);
+            }
+            req.format(api::ResponseFormat::Json)
+                .drilldowns(parse_names(&drilldowns)?)
+                .named_sets(named_sets)
+                .measures(parse_names(&measures)?)
+                .cuts(parse_names(&cuts)?)
+                .properties(parse_names(&properties)?);
+
+            if parents { req.parents(true); }
+            if nonempty { req.nonempty(true); }
+            if distinct { req.distinct(true); }
+            if sparse { req.sparse(true); }
+
+            let mut aggs = Vec::new();
+            for base_url in &[left, right] {
+                let client = Client::with_transport(transport.clone(), base_url.clone());
+                req.base_url(client.base_url());
+
+                if config.verbose {
+                    println!("{}", req.url()?);
+                }
+                let agg: aggregate::Aggregation = serde_json::from_str(&client.aggregate(&req)?)?;
+                aggs.push(agg);
+            }
+
+            let comparison = compare::Comparison::new(&aggs[0], &aggs[1], &tolerance);
+            if !comparison.is_match() {
+                println!("{}", comparison);
+                return Err(CliError::Mismatch("Query results differ".to_owned()).into());
+            }
+            comparison.to_string()
+        },
+        Command::Snapshot {cmd} => {
+            let client = Client::with_transport(transport, config.base_url.unwrap());
+
+            match cmd {
+                SnapshotCommand::Record {queries, dir} => {
+                    let queries = spec::load(Path::new(&queries))?;
+                    let dir = dir.unwrap_or_else(|| snapshot::DEFAULT_DIR.to_owned());
+
+                    let recorded = snapshot::record(&client, &queries, Path::new(&dir), config.verbose)?;
+                    for path in &recorded.removed {
+                        println!("Removed old snapshot {}", path.display());
+                    }
+                    format!("Recorded {} snapshots in {}", recorded.paths.len(), dir)
+                },
+                SnapshotCommand::Verify {dir, abs_tolerance, rel_tolerance} => {
+                    let tolerance = tolerance(abs_tolerance, rel_tolerance)?;
+                    let dir = dir.unwrap_or_else(|| snapshot::DEFAULT_DIR.to_owned());
+
+                    let results = snapshot::verify(&client, Path::new(&dir), &tolerance, config.verbose)?;
+                    let summary = snapshot::VerifySummary { results: &results };
+                    println!("{}", summary);
+
+                    let failed = summary.failed();
+                    if failed > 0 {
+                        return Err(CliError::Mismatch(
+                            format!("{} of {} snapshots changed or failed", failed, results.len())
+                        ).into());
+                    }
+                    "Snapshots verified".to_owned()
+                },
+            }
+        },
+        Command::Batch {queries, jobs, no_validate} => {
+            let queries = spec::load(Path::new(&queries))?;
+            batch::check_outputs(&queries)?;
+
+            let options = batch::BatchOptions {
+                jobs: jobs.unwrap_or(1),
+                validate: !no_validate,
+                verbose: config.verbose,
+            };
+            let client = Client::with_transport(transport, config.base_url.unwrap());
+
+            let results = batch::run(&client, &queries, &options);
+            let summary = batch::Summary { results: &results };
+            println!("{}", summary);
+
+            let failed = summary.failed();
+            if failed > 0 {
+                return Err(CliError::TestFailure(
+                    format!("{} of {} batch queries failed", failed, results.len())
+                ).into());
+            }
+            "Batch complete".to_owned()
+        },
+        Command::Mock {cubes, fixtures, port, secret} => {
+            let server = mock::MockServer::new(
+                Path::new(&cubes),
+                fixtures.map(PathBuf::from),
+                secret,
+                config.verbose,
+            )?;
+
+            let port = port.unwrap_or(mock::DEFAULT_PORT);
+            let listener = TcpListener::bind(("127.0.0.1", port))
+                .map_err(|err| CliError::Config(format!("Could not listen on port {}: {}", port, err)))?;
+            println!("Mock mondrian-rest server on http://127.0.0.1:{}/", port);
+
+            mock::serve(Arc::new(server), listener)?;
+            "Mock server stopped".to_owned()
+        },
+        Command::Shell => {
+            let client = Client::with_transport(transport, config.base_url.unwrap());
+            shell::run(&client, config.verbose)?;
+            "Exiting shell".to_owned()
         },
         Command::Flush {secret} => {
             if config.verbose {
                 println!("secret: {}", secret.as_ref().unwrap());
             }
-            flush(&client, config.base_url.unwrap(), secret.unwrap())?;
+            let client = Client::with_transport(transport, config.base_url.unwrap());
+            client.flush(secret.unwrap())?;
             "Flush complete".to_owned()
         },
         Command::Query {
             cube_name,
+            from_url,
+            load,
+            save,
             drilldowns,
+            named_sets,
             measures,
             cuts,
             properties,
@@ -135,40 +373,89 @@ fn run() -> Result<(), Error> {
             nonempty,
             distinct,
             sparse,
+            no_validate,
             format,
             } =>
         {
-            let drilldowns = drilldowns.iter()
-                .map(|s| s.parse())
-                .collect::<Result<Vec<_>, Error>>()?;
-            let measures = measures.iter()
-                .map(|s| s.parse())
-                .collect::<Result<Vec<_>, Error>>()?;
-            let cuts = cuts.iter()
-                .map(|s| s.parse())
-                .collect::<Result<Vec<_>, Error>>()?;
-            let properties = properties.iter()
-                .map(|s| s.parse())
-                .collect::<Result<Vec<_>, Error>>()?;
-
-            let mut req = api::query(config.base_url.unwrap());
-            req.cube(cube_name)
-                .drilldowns(drilldowns)
+            let drilldowns = parse_names(&drilldowns)?;
+            let measures = parse_names(&measures)?;
+            let cuts = parse_names(&cuts)?;
+            let properties = parse_names(&properties)?;
+
+            // Requests go to the base url of the query, which
+            // for a query from a url is the url's.
+            let client = Client::with_transport(transport, config.base_url.clone().unwrap_or_default());
+
+            // fetched once, for telling named sets in a url from
+            // levels and for validation
+            let mut cube = None;
+
+            // Options from the cli are added on top of the query
+            // from the url or file; flags can only be turned on.
+            let mut req = match (from_url, load) {
+                (Some(url), _) => {
+                    let url = Url::parse(&url)?;
+                    let req = QueryBuilder::try_from(&url)?;
+                    if no_validate {
+                        req
+                    } else {
+                        let description = cube_description(&client, &req, config.verbose)?;
+                        let req = QueryBuilder::from_url_with_cube(&url, &description)?;
+                        cube = Some(description);
+                        req
+                    }
+                },
+                // a configured server replaces the saved one
+                (None, Some(path)) => spec::load_query_for(Path::new(&path), config.base_url)?,
+                (None, None) => {
+                    let mut req = client.query();
+                    req.format(config.format);
+                    req
+                },
+            };
+            if let Some(cube_name) = cube_name {
+                req.cube(cube_name);
+                cube = None;
+            }
+            if let Some(format) = format {
+                req.format(format);
+            }
+            req.drilldowns(drilldowns)
+                .named_sets(named_sets)
                 .measures(measures)
                 .cuts(cuts)
-                .properties(properties)
-                .debug(debug)
-                .parents(parents)
-                .nonempty(nonempty)
-                .distinct(distinct)
-                .sparse(sparse)
-                .format(format);
+                .properties(properties);
+
+            if debug { req.debug(true); }
+            if parents { req.parents(true); }
+            if nonempty { req.nonempty(true); }
+            if distinct { req.distinct(true); }
+            if sparse { req.sparse(true); }
+
+            if !no_validate {
+                let cube = match cube {
+                    Some(cube) => cube,
+                    None => cube_description(&client, &req, config.verbose)?,
+                };
+                req.validate(&cube)?;
+            }
+
+            if let Some(path) = save {
+                spec::save_query(Path::new(&path), &req)?;
+            }
 
-            let url = req.url()?;
             if config.verbose {
-                println!("{}", url);
+                println!("{}", req.url()?);
+            }
+
+            let resp = client.aggregate(&req)?;
+
+            if *req.response_format() == api::ResponseFormat::Table {
+                let agg: aggregate::Aggregation = serde_json::from_str(&resp)?;
+                agg.to_string()
+            } else {
+                resp
             }
-            exec_query(&client, url)?
         },
     };
 
@@ -176,94 +463,68 @@ fn run() -> Result<(), Error> {
     Ok(())
 }
 
-fn test_cube(client: &Client,
-    cube_description: &CubeDescription,
-    base_url: &str,
-    cube_name: &str,
-    verbose: bool,
-    ) -> Result<(), Error>
-{
-
-    // Test strategy, to prevent all combinations being tested:
-    // 2 runs
-    // - all dims with one measure
-    // - one dim with all measures
-
-    let test_drill_mea_prop = cube_description.test_drill_mea_prop();
+/// Description of the cube being queried
+fn cube_description(client: &Client, req: &QueryBuilder, verbose: bool) -> Result<CubeDescription, Error> {
+    let url = req.cube_description_url()?;
     if verbose {
-        println!("{}", test_drill_mea_prop);
+        println!("{}", url);
     }
+    Ok(serde_json::from_str(&client.get(url)?)?)
+}
 
-    let drilldowns = test_drill_mea_prop.dims.iter()
-        .map(|s| s.parse())
-        .collect::<Result<Vec<Drilldown>, Error>>()?;
-    let measures = test_drill_mea_prop.meas.iter()
-        .map(|s| s.parse())
-        .collect::<Result<Vec<Measure>, Error>>()?;
-    let properties = test_drill_mea_prop.props.iter()
-        .map(|s| s.parse())
-        .collect::<Result<Vec<Property>, Error>>()?;
-
-    for drilldown in drilldowns {
-        let mut req = api::query(base_url.to_owned());
-        req.cube(cube_name)
-            .drilldown(drilldown)
-            .measures(measures.clone());
-
-
-        if verbose {
-            println!("Test url:\n{}\n", req.url()?);
-        }
+/// Cube filters only apply when covering all cubes
+fn cube_filter(
+    cube_name: &Option<String>,
+    include: &[String],
+    exclude: &[String],
+    skip_file: &Option<String>,
+    ) -> Result<CubeFilter, Error>
+{
+    let filter = CubeFilter::new(include, exclude, skip_file.as_ref().map(Path::new))?;
 
-        let url = req.url()?;
-        client.get(url).send().map(|_|())?;
+    if cube_name.is_some() && !filter.is_empty() {
+        return Err(CliError::Config(
+            "--include, --exclude, and --skip-file can't be used with a cube name".to_owned()
+        ).into());
     }
 
-    for property in properties {
-        let mut req = api::query(base_url.to_owned());
-        req.cube(cube_name)
-            .drilldown(property.drill_level())
-            .measures(measures.clone())
-            .property(property);
-
-        if verbose {
-            println!("Test url:\n{}\n", req.url()?);
-        }
+    Ok(filter)
+}
 
-        let url = req.url()?;
-        client.get(url).send().map(|_|())?;
+fn tolerance(absolute: Option<f64>, relative: Option<f64>) -> Result<compare::Tolerance, Error> {
+    if absolute.iter().chain(relative.iter()).any(|tol| !tol.is_finite() || *tol < 0.0) {
+        return Err(CliError::Config("Tolerance must be a non-negative number".to_owned()).into());
     }
-
-    println!("{}: passed", cube_name);
-    Ok(())
+    Ok(compare::Tolerance {
+        absolute: absolute.unwrap_or(0.0),
+        relative: relative.unwrap_or(0.0),
+    })
 }
 
-/// Execute the call and return
-/// the body as unparsed string
-pub fn exec_query(client: &Client, url: Url) -> Result<String, Error> {
-    let mut resp = client.get(url).send()?;
-
-    // TODO return a good error
-    ensure!(resp.status().is_success(), format!("[{}]:\n{}", resp.status(), api::format_backtrace(resp.text()?)));
-
-    Ok(resp.text()?)
+/// Parses names (drilldowns, measures, etc.) from the cli
+fn parse_names<T>(names: &[String]) -> Result<Vec<T>, Error>
+    where T: FromStr<Err=Error>,
+{
+    names.iter()
+        .map(|s| s.parse())
+        .collect()
 }
 
-pub fn flush<S: Into<String>>(client: &Client, base_url: S, secret: S) -> Result<(), Error> {
-    let mut base_url = base_url.into().clone();
-    api::add_trailing_slash(&mut base_url);
-
-    let mut url = Url::parse(&base_url)?;
-    url = url.join("flush")?;
-
-    url.query_pairs_mut().append_pair("secret", &secret.into());
-    //println!("{}", url.as_str());
-
-    let mut resp = client.get(url).send()?;
-
-    // TODO return a good error
-    ensure!(resp.status().is_success(), format!("[{}]:\n{}", resp.status(), api::format_backtrace(resp.text()?)));
+/// Loads all cube descriptions, from a base url or from a file
+/// saved from `describe --raw`.
+fn load_schema(transport: &Transport, source: &str, verbose: bool) -> Result<CubeDescriptions, Error> {
+    let resp = if source.starts_with("http://") || source.starts_with("https://") {
+        let client = Client::with_transport(transport.clone(), source);
+        let url = client.query().url()?;
+        if verbose {
+            println!("{}", url);
+        }
+        client.get(url)?
+    } else {
+        fs::read_to_string(source)
+            .map_err(|err| CliError::Config(format!("Could not read schema snapshot {:?}: {}", source, err)))?
+    };
 
-    Ok(())
+    serde_json::from_str(&resp)
+        .map_err(|err| format_err!("Could not parse schema from {}: {}", source, err))
 }
-
@@ -0,0 +1,535 @@
+//! Fake mondrian-rest server, for working without the real one
+//! (e.g. frontend development, or testing this cli).
+//!
+//! The schema comes from a cube descriptions json file (as saved by
+//! `describe --raw`). Member and aggregate responses come from an
+//! optional fixture directory, laid out like the api urls:
+//!
+//! ```text
+//! <fixtures>/cubes/<cube>/aggregate.json
+//! <fixtures>/cubes/<cube>/dimensions/<dim>/hierarchies/<hier>/levels/<level>/members.json
+//! ```
+//!
+//! Without an aggregate fixture, the response is generated from the
+//! query: one axis per drilldown, with the members from the members
+//! fixture of its level (if any), and every value 0. Csv and
+//! jsonrecords responses are rendered from the json response.
+//!
+//! Queries are checked against the schema like the query subcommand
+//! does, and bad names get a mondrian-style json error.
+//!
+//! Only GET is supported, one request per connection.
+
+use failure::Error;
+use reqwest::{StatusCode, Url};
+use serde_json::{self, Value};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use url::percent_encoding::percent_decode;
+
+use mondrian_rest::aggregate::{self, Aggregation};
+use mondrian_rest::api::{Drilldown, LevelName, QueryBuilder, ResponseFormat};
+use mondrian_rest::error::CliError;
+use mondrian_rest::schema::{CubeDescription, CubeDescriptions};
+
+pub const DEFAULT_PORT: u16 = 5000;
+
+#[derive(Debug)]
+pub struct MockServer {
+    /// Raw cube descriptions, served as they are
+    cubes: Value,
+    schema: CubeDescriptions,
+    fixtures: Option<PathBuf>,
+    secret: Option<String>,
+    verbose: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MockResponse {
+    pub status: StatusCode,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl MockResponse {
+    fn json(status: StatusCode, body: &Value) -> Self {
+        MockResponse {
+            status,
+            content_type: "application/json",
+            body: body.to_string(),
+        }
+    }
+
+    /// Error in the json format mondrian-rest uses
+    fn error<S: Into<String>>(status: StatusCode, msg: S) -> Self {
+        MockResponse::json(status, &json!({ "error": msg.into() }))
+    }
+}
+
+impl MockServer {
+    pub fn new(cubes_path: &Path, fixtures: Option<PathBuf>, secret: Option<String>, verbose: bool) -> Result<Self, Error> {
+        let s = fs::read_to_string(cubes_path)
+            .map_err(|err| CliError::Config(format!("Could not read cube descriptions {:?}: {}", cubes_path, err)))?;
+
+        MockServer::from_json(&s, fixtures, secret, verbose)
+            .map_err(|err| CliError::Config(format!("Invalid cube descriptions {:?}: {}", cubes_path, err)).into())
+    }
+
+    fn from_json(s: &str, fixtures: Option<PathBuf>, secret: Option<String>, verbose: bool) -> Result<Self, Error> {
+        Ok(MockServer {
+            cubes: serde_json::from_str(s)?,
+            schema: serde_json::from_str(s)?,
+            fixtures,
+            secret,
+            verbose,
+        })
+    }
+
+    /// Answers a request for the path and query of a url,
+    /// e.g. `/cubes/exports/aggregate.json?...`
+    pub fn respond(&self, target: &str) -> MockResponse {
+        let url = match Url::parse("http://localhost").and_then(|base| base.join(target)) {
+            Ok(url) => url,
+            Err(err) => return MockResponse::error(StatusCode::BadRequest, err.to_string()),
+        };
+
+        let mut path: Vec<String> = url.path_segments()
+            .map(|segments| {
+                segments
+                    .map(|segment| percent_decode(segment.as_bytes()).decode_utf8_lossy().into_owned())
+                    .collect()
+            })
+            .unwrap_or_default();
+        // trailing slash
+        if path.last().map(|segment| segment.is_empty()).unwrap_or(false) {
+            path.pop();
+        }
+
+        let path: Vec<&str> = path.iter().map(|segment| segment.as_str()).collect();
+        match path.as_slice() {
+            ["flush"] => self.flush(&url),
+            ["cubes"] => MockResponse::json(StatusCode::Ok, &self.cubes),
+            ["cubes", cube_name] => {
+                match self.raw_cube(cube_name) {
+                    Some(cube) => MockResponse::json(StatusCode::Ok, cube),
+                    None => cube_not_found(cube_name),
+                }
+            },
+            ["cubes", cube_name, aggregate] if aggregate.starts_with("aggregate.") => {
+                self.aggregate(cube_name, &url)
+            },
+            ["cubes", cube_name, "dimensions", dim, "hierarchies", hier, "levels", level, "members"] => {
+                self.members(cube_name, &LevelName::new(*dim, *hier, *level))
+            },
+            _ => MockResponse::error(StatusCode::NotFound, format!("Not found: {}", url.path())),
+        }
+    }
+
+    fn flush(&self, url: &Url) -> MockResponse {
+        let secret = url.query_pairs()
+            .find(|(key, _)| key == "secret")
+            .map(|(_, value)| value.into_owned());
+
+        match self.secret {
+            Some(ref expected) if secret.as_ref() != Some(expected) => {
+                MockResponse::error(StatusCode::Forbidden, "Invalid secret")
+            },
+            _ => MockResponse::json(StatusCode::Ok, &json!({})),
+        }
+    }
+
+    fn raw_cube(&self, cube_name: &str) -> Option<&Value> {
+        self.cubes.get("cubes")
+            .and_then(|cubes| cubes.as_array())
+            .and_then(|cubes| cubes.iter().find(|cube| cube.get("name") == Some(&Value::from(cube_name))))
+    }
+
+    fn cube(&self, cube_name: &str) -> Option<&CubeDescription> {
+        self.schema.cubes.iter().find(|cube| cube.name == cube_name)
+    }
+
+    fn members(&self, cube_name: &str, level_name: &LevelName) -> MockResponse {
+        let cube = match self.cube(cube_name) {
+            Some(cube) => cube,
+            None => return cube_not_found(cube_name),
+        };
+        if let Err(err) = cube.check_level(level_name) {
+            return MockResponse::error(StatusCode::NotFound, err);
+        }
+
+        match self.members_fixture(cube_name, level_name) {
+            Ok(Some(members)) => MockResponse::json(StatusCode::Ok, &members),
+            Ok(None) => MockResponse::json(StatusCode::Ok, &json!({
+                "name": level_name.level(),
+                "caption": level_name.level(),
+                "members": [],
+            })),
+            Err(err) => MockResponse::error(StatusCode::InternalServerError, err.to_string()),
+        }
+    }
+
+    fn aggregate(&self, cube_name: &str, url: &Url) -> MockResponse {
+        let cube = match self.cube(cube_name) {
+            Some(cube) => cube,
+            None => return cube_not_found(cube_name),
+        };
+
+        // the same checks as the query subcommand
+        let req = match QueryBuilder::from_url_with_cube(url, cube).and_then(|req| req.validate(cube).map(|_| req)) {
+            Ok(req) => req,
+            Err(err) => return MockResponse::error(StatusCode::BadRequest, err.to_string()),
+        };
+
+        match self.aggregation(cube_name, url) {
+            Ok(json) => render_aggregation(&json, req.response_format()),
+            Err(err) => MockResponse::error(StatusCode::InternalServerError, err.to_string()),
+        }
+    }
+
+    /// Aggregate fixture of the cube, or a response generated
+    /// from the drilldowns and measures of the query
+    fn aggregation(&self, cube_name: &str, url: &Url) -> Result<Value, Error> {
+        if let Some(fixture) = self.fixture(&["cubes", cube_name, "aggregate.json"])? {
+            return Ok(fixture);
+        }
+
+        let mut measures = Vec::new();
+        let mut axes = Vec::new();
+        let mut axis_dimensions = vec![json!({
+            "name": "Measures", "caption": "Measures", "type": "measures", "level": "MeasuresLevel", "level_depth": 0,
+        })];
+
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "measures[]" => measures.push(json!({
+                    "name": value, "full_name": format!("[Measures].[{}]", value), "caption": value,
+                })),
+                "drilldown[]" => {
+                    // named sets have no members fixture
+                    let level_name = value.parse::<Drilldown>().ok().map(|drilldown| drilldown.level_name().clone());
+                    let (name, level, members) = match level_name {
+                        Some(ref level_name) => {
+                            let members = self.members_fixture(cube_name, level_name)?
+                                .and_then(|members| members.get("members").cloned())
+                                .unwrap_or_else(|| json!([]));
+                            (level_name.dimension().to_owned(), level_name.level().to_owned(), members)
+                        },
+                        None => (value.clone().into_owned(), value.clone().into_owned(), json!([])),
+                    };
+                    axis_dimensions.push(json!({
+                        "name": name, "caption": name, "type": "standard", "level": level, "level_depth": 1,
+                    }));
+                    axes.push(json!({ "members": members }));
+                },
+                _ => {},
+            }
+        }
+
+        // values are nested with the last axis outermost
+        let mut values = json!(0);
+        let mut lengths: Vec<usize> = vec![measures.len()];
+        lengths.extend(axes.iter().map(|axis| axis["members"].as_array().map(|m| m.len()).unwrap_or(0)));
+        for len in lengths {
+            values = Value::Array(vec![values; len]);
+        }
+
+        axes.insert(0, json!({ "members": measures }));
+
+        Ok(json!({
+            "axes": axes,
+            "axis_dimensions": axis_dimensions,
+            "values": values,
+        }))
+    }
+
+    fn members_fixture(&self, cube_name: &str, level_name: &LevelName) -> Result<Option<Value>, Error> {
+        self.fixture(&[
+            "cubes", cube_name,
+            "dimensions", level_name.dimension(),
+            "hierarchies", level_name.hierarchy(),
+            "levels", level_name.level(),
+            "members.json",
+        ])
+    }
+
+    fn fixture(&self, path: &[&str]) -> Result<Option<Value>, Error> {
+        let fixtures = match self.fixtures {
+            Some(ref fixtures) => fixtures,
+            None => return Ok(None),
+        };
+        let path = path.iter().fold(fixtures.clone(), |path, segment| path.join(segment));
+
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let s = fs::read_to_string(&path)?;
+        let value = serde_json::from_str(&s)
+            .map_err(|err| format_err!("Invalid fixture {:?}: {}", path, err))?;
+
+        Ok(Some(value))
+    }
+
+    fn handle(&self, mut stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+
+        // headers aren't needed
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let resp = match (parts.next(), parts.next()) {
+            (Some("GET"), Some(target)) => self.respond(target),
+            _ => MockResponse::error(StatusCode::MethodNotAllowed, "Only GET is supported"),
+        };
+
+        if self.verbose {
+            println!("{} -> {}", request_line.trim(), resp.status);
+        }
+
+        write!(stream,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            resp.status, resp.content_type, resp.body.len(), resp.body,
+        )?;
+        stream.flush()
+    }
+}
+
+/// Serves requests until the process is stopped, one thread
+/// per connection.
+pub fn serve(server: Arc<MockServer>, listener: TcpListener) -> Result<(), Error> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let server = Arc::clone(&server);
+        thread::spawn(move || {
+            if let Err(err) = server.handle(stream) {
+                eprintln!("Mock server connection error: {}", err);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn cube_not_found(cube_name: &str) -> MockResponse {
+    MockResponse::error(StatusCode::NotFound, format!("Cube {:?} not found", cube_name))
+}
+
+fn render_aggregation(json: &Value, format: &ResponseFormat) -> MockResponse {
+    match *format {
+        ResponseFormat::Json => return MockResponse::json(StatusCode::Ok, json),
+        ResponseFormat::Table => return MockResponse::error(StatusCode::NotFound, "Unknown format table"),
+        _ => {},
+    }
+
+    let agg: Aggregation = match serde_json::from_value(json.clone()) {
+        Ok(agg) => agg,
+        Err(err) => return MockResponse::error(StatusCode::InternalServerError, format!("Invalid aggregate fixture: {}", err)),
+    };
+
+    let mut header: Vec<String> = agg.drilldowns().iter().map(|dim| dim.level.clone()).collect();
+    header.extend(agg.measures().iter().map(|mea| mea.name.clone()));
+
+    let rows: Vec<Vec<Value>> = agg.rows().iter()
+        .map(|row| {
+            let mut cells: Vec<Value> = row.members.iter()
+                .map(|member| Value::from(member.caption.clone()))
+                .collect();
+            cells.extend(row.values.iter().map(|value| json!(value)));
+            cells
+        })
+        .collect();
+
+    if *format == ResponseFormat::JsonRecords {
+        let data: Vec<Value> = rows.into_iter()
+            .map(|cells| {
+                let record = header.iter().cloned().zip(cells).collect();
+                Value::Object(record)
+            })
+            .collect();
+        return MockResponse::json(StatusCode::Ok, &json!({ "data": data }));
+    }
+
+    let mut csv = csv_line(header.iter().map(|cell| cell.as_str()));
+    for cells in &rows {
+        let cells: Vec<String> = cells.iter()
+            .map(|cell| match *cell {
+                Value::String(ref s) => s.clone(),
+                ref value => aggregate::format_value(value.as_f64()),
+            })
+            .collect();
+        csv.push_str(&csv_line(cells.iter().map(|cell| cell.as_str())));
+    }
+
+    MockResponse {
+        status: StatusCode::Ok,
+        content_type: "text/csv",
+        body: csv,
+    }
+}
+
+fn csv_line<'a, I: Iterator<Item=&'a str>>(cells: I) -> String {
+    let cells: Vec<String> = cells
+        .map(|cell| {
+            if cell.contains(',') || cell.contains('"') || cell.contains('\n') {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.to_owned()
+            }
+        })
+        .collect();
+    format!("{}\n", cells.join(","))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use mondrian_rest::api;
+    use mondrian_rest::test_support::{fixture_dir, EXPORTS_JSON};
+
+    fn server(fixtures: Option<PathBuf>) -> MockServer {
+        let cubes = format!(r#"{{ "cubes": [{}] }}"#, EXPORTS_JSON);
+        MockServer::from_json(&cubes, fixtures, Some("secret123".to_owned()), false).unwrap()
+    }
+
+    /// Fixtures with the members of Geography.State
+    fn fixtures(name: &str) -> PathBuf {
+        let dir = fixture_dir(name);
+        let level_dir = dir.join("cubes/exports/dimensions/Geography/hierarchies/Geography/levels/State");
+        fs::create_dir_all(&level_dir).unwrap();
+        fs::write(level_dir.join("members.json"), r#"{
+            "name": "State",
+            "caption": "State",
+            "members": [
+                { "name": "Alabama", "full_name": "[Geography].[Alabama]", "caption": "Alabama", "key": 1 },
+                { "name": "Alaska", "full_name": "[Geography].[Alaska]", "caption": "Alaska", "key": 2 }
+            ]
+        }"#).unwrap();
+        dir
+    }
+
+    fn query_path(format: api::ResponseFormat) -> String {
+        let mut req = api::query("http://localhost/".to_owned());
+        req.cube("exports")
+            .drilldown("Geography.State".parse().unwrap())
+            .measure("Dollars Sum".parse().unwrap())
+            .measure("Count".parse().unwrap())
+            .format(format);
+        let url = req.url().unwrap();
+        format!("{}?{}", url.path(), url.query().unwrap())
+    }
+
+    #[test]
+    fn test_mock_describe() {
+        let server = server(None);
+
+        let resp = server.respond("/cubes");
+        assert_eq!(resp.status, StatusCode::Ok);
+        let cubes: CubeDescriptions = serde_json::from_str(&resp.body).unwrap();
+        assert_eq!(cubes.cubes[0].name, "exports");
+
+        let resp = server.respond("/cubes/exports/");
+        let cube: CubeDescription = serde_json::from_str(&resp.body).unwrap();
+        assert_eq!(cube.name, "exports");
+
+        assert_eq!(server.respond("/cubes/imports").status, StatusCode::NotFound);
+        assert_eq!(server.respond("/nothing/here").status, StatusCode::NotFound);
+    }
+
+    #[test]
+    fn test_mock_flush() {
+        let server = server(None);
+
+        assert_eq!(server.respond("/flush?secret=secret123").status, StatusCode::Ok);
+        assert_eq!(server.respond("/flush?secret=wrong").status, StatusCode::Forbidden);
+        assert_eq!(server.respond("/flush").status, StatusCode::Forbidden);
+    }
+
+    #[test]
+    fn test_mock_members() {
+        let server = server(Some(fixtures("mock-members")));
+
+        let resp = server.respond("/cubes/exports/dimensions/Geography/hierarchies/Geography/levels/State/members");
+        assert_eq!(resp.status, StatusCode::Ok);
+        assert!(resp.body.contains("Alaska"));
+
+        // no fixture
+        let resp = server.respond("/cubes/exports/dimensions/Geography/hierarchies/Geography/levels/County/members");
+        assert_eq!(resp.status, StatusCode::Ok);
+        assert!(resp.body.contains(r#""members":[]"#));
+
+        let resp = server.respond("/cubes/exports/dimensions/Geography/hierarchies/Geography/levels/City/members");
+        assert_eq!(resp.status, StatusCode::NotFound);
+    }
+
+    #[test]
+    fn test_mock_aggregate() {
+        let server = server(Some(fixtures("mock-aggregate")));
+
+        let resp = server.respond(&query_path(api::ResponseFormat::Json));
+        assert_eq!(resp.status, StatusCode::Ok);
+        let agg: Aggregation = serde_json::from_str(&resp.body).unwrap();
+        assert_eq!(agg.to_string(),
+            "Geography  Dollars Sum  Count\n\
+            ---------  -----------  -----\n\
+            Alabama              0      0\n\
+            Alaska               0      0\n"
+        );
+
+        let resp = server.respond(&query_path(api::ResponseFormat::Csv));
+        assert_eq!(resp.body, "State,Dollars Sum,Count\nAlabama,0,0\nAlaska,0,0\n");
+
+        let resp = server.respond(&query_path(api::ResponseFormat::JsonRecords));
+        let records: Value = serde_json::from_str(&resp.body).unwrap();
+        assert_eq!(records["data"][1], json!({ "State": "Alaska", "Dollars Sum": 0.0, "Count": 0.0 }));
+
+        // names are checked against the schema
+        let resp = server.respond("/cubes/exports/aggregate.json?drilldown%5B%5D=%5BGeography%5D.%5BCity%5D&measures%5B%5D=Count");
+        assert_eq!(resp.status, StatusCode::BadRequest);
+        assert!(resp.body.contains("City"));
+    }
+
+    /// Raw GET request, returning the status line and body
+    fn get(addr: &str, target: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", target, addr).unwrap();
+
+        let mut resp = String::new();
+        io::Read::read_to_string(&mut stream, &mut resp).unwrap();
+
+        let status = resp.lines().next().unwrap().to_owned();
+        let body = resp.split_once("\r\n\r\n").unwrap().1.to_owned();
+        (status, body)
+    }
+
+    #[test]
+    fn test_mock_server_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = Arc::new(server(Some(fixtures("mock-http"))));
+        thread::spawn(move || serve(server, listener));
+
+        let (status, body) = get(&addr, "/cubes/");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        let cubes: CubeDescriptions = serde_json::from_str(&body).unwrap();
+        assert_eq!(cubes.cubes.len(), 1);
+
+        let (status, body) = get(&addr, &query_path(api::ResponseFormat::Csv));
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(body, "State,Dollars Sum,Count\nAlabama,0,0\nAlaska,0,0\n");
+
+        let (status, _) = get(&addr, "/flush?secret=wrong");
+        assert_eq!(status, "HTTP/1.1 403 Forbidden");
+    }
+}
@@ -0,0 +1,289 @@
+//! Differences between two schemas (e.g. staging and production),
+//! for reviewing schema changes before they're deployed.
+//!
+//! Items are matched by name at each level of the schema: cubes,
+//! dimensions, hierarchies, levels, properties, measures, and named
+//! sets. Besides added and removed items, changed annotations,
+//! measure aggregators, and named set levels are reported.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use super::{CubeDescription, CubeDescriptions, Dimension, Hierarchy, Level, Measure, NamedSet};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Added { kind: &'static str, path: String },
+    Removed { kind: &'static str, path: String },
+    Changed { kind: &'static str, path: String, from: String, to: String },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Change::Added{kind, ref path} => write!(f, "+ {} {}", kind, path),
+            Change::Removed{kind, ref path} => write!(f, "- {} {}", kind, path),
+            Change::Changed{kind, ref path, ref from, ref to} => {
+                write!(f, "~ {} {}: {} -> {}", kind, path, from, to)
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SchemaDiff {
+    pub changes: Vec<Change>,
+}
+
+impl SchemaDiff {
+    pub fn new(left: &CubeDescriptions, right: &CubeDescriptions) -> Self {
+        let mut changes = Vec::new();
+
+        let cubes = match_names("cube", "", &left.cubes, &right.cubes, |cube| &cube.name, &mut changes);
+        for (l, r) in cubes {
+            diff_cube(l, r, &mut changes);
+        }
+
+        SchemaDiff { changes }
+    }
+}
+
+impl fmt::Display for SchemaDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.changes.is_empty() {
+            return writeln!(f, "No differences");
+        }
+
+        let mut added = 0;
+        let mut removed = 0;
+        let mut changed = 0;
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+            match *change {
+                Change::Added{..} => added += 1,
+                Change::Removed{..} => removed += 1,
+                Change::Changed{..} => changed += 1,
+            }
+        }
+
+        writeln!(f, "\n{} differences: {} added, {} removed, {} changed",
+            self.changes.len(), added, removed, changed)
+    }
+}
+
+fn diff_cube(left: &CubeDescription, right: &CubeDescription, changes: &mut Vec<Change>) {
+    let path = left.name.as_str();
+    diff_annotations(path, &left.annotations, &right.annotations, changes);
+
+    let dimensions = match_names("dimension", path, &left.dimensions, &right.dimensions, |dim| &dim.name, changes);
+    for (l, r) in dimensions {
+        diff_dimension(&child(path, &l.name), l, r, changes);
+    }
+
+    let measures = match_names("measure", path, &left.measures, &right.measures, |mea| &mea.name, changes);
+    for (l, r) in measures {
+        diff_measure(&child(path, &l.name), l, r, changes);
+    }
+
+    let named_sets = match_names("named set", path, &left.named_sets, &right.named_sets, |set| &set.name, changes);
+    for (l, r) in named_sets {
+        diff_named_set(&child(path, &l.name), l, r, changes);
+    }
+}
+
+fn diff_dimension(path: &str, left: &Dimension, right: &Dimension, changes: &mut Vec<Change>) {
+    diff_annotations(path, &left.annotations, &right.annotations, changes);
+
+    let hierarchies = match_names("hierarchy", path, &left.hierarchies, &right.hierarchies, |hier| &hier.name, changes);
+    for (l, r) in hierarchies {
+        diff_hierarchy(&child(path, &l.name), l, r, changes);
+    }
+}
+
+fn diff_hierarchy(path: &str, left: &Hierarchy, right: &Hierarchy, changes: &mut Vec<Change>) {
+    let levels = match_names("level", path, &left.levels, &right.levels, |lvl| &lvl.name, changes);
+    for (l, r) in levels {
+        diff_level(&child(path, &l.name), l, r, changes);
+    }
+}
+
+fn diff_level(path: &str, left: &Level, right: &Level, changes: &mut Vec<Change>) {
+    diff_annotations(path, &left.annotations, &right.annotations, changes);
+
+    match_names("property", path, &left.properties, &right.properties, |prop| prop, changes);
+}
+
+fn diff_measure(path: &str, left: &Measure, right: &Measure, changes: &mut Vec<Change>) {
+    diff_annotations(path, &left.annotations, &right.annotations, changes);
+
+    if left.aggregator != right.aggregator {
+        changes.push(Change::Changed {
+            kind: "aggregator",
+            path: path.to_owned(),
+            from: show_aggregator(&left.aggregator),
+            to: show_aggregator(&right.aggregator),
+        });
+    }
+}
+
+fn diff_named_set(path: &str, left: &NamedSet, right: &NamedSet, changes: &mut Vec<Change>) {
+    diff_annotations(path, &left.annotations, &right.annotations, changes);
+
+    let from = named_set_level(left);
+    let to = named_set_level(right);
+    if from != to {
+        changes.push(Change::Changed { kind: "named set level", path: path.to_owned(), from, to });
+    }
+}
+
+/// Reports items removed from and added to a list, and returns
+/// the pairs of items present in both, to be compared further.
+fn match_names<'a, T, F>(
+    kind: &'static str,
+    parent: &str,
+    left: &'a [T],
+    right: &'a [T],
+    name: F,
+    changes: &mut Vec<Change>,
+    ) -> Vec<(&'a T, &'a T)>
+    where F: Fn(&T) -> &String,
+{
+    let mut pairs = Vec::new();
+
+    for l in left {
+        match right.iter().find(|r| name(r) == name(l)) {
+            Some(r) => pairs.push((l, r)),
+            None => changes.push(Change::Removed { kind, path: child(parent, name(l)) }),
+        }
+    }
+
+    for r in right {
+        if !left.iter().any(|l| name(l) == name(r)) {
+            changes.push(Change::Added { kind, path: child(parent, name(r)) });
+        }
+    }
+
+    pairs
+}
+
+fn diff_annotations(
+    path: &str,
+    left: &HashMap<String, String>,
+    right: &HashMap<String, String>,
+    changes: &mut Vec<Change>,
+    )
+{
+    // sorted, so that output doesn't depend on hash order
+    let keys: BTreeSet<&String> = left.keys().chain(right.keys()).collect();
+
+    for key in keys {
+        let kind = "annotation";
+        let path = child(path, key);
+
+        match (left.get(key), right.get(key)) {
+            (Some(_), None) => changes.push(Change::Removed { kind, path }),
+            (None, Some(_)) => changes.push(Change::Added { kind, path }),
+            (Some(from), Some(to)) if from != to => {
+                changes.push(Change::Changed { kind, path, from: format!("{:?}", from), to: format!("{:?}", to) });
+            },
+            _ => (),
+        }
+    }
+}
+
+fn child(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{} / {}", parent, name)
+    }
+}
+
+fn show_aggregator(aggregator: &Option<String>) -> String {
+    aggregator.clone().unwrap_or_else(|| "none".to_owned())
+}
+
+fn named_set_level(named_set: &NamedSet) -> String {
+    format!("[{}].[{}].[{}]", named_set.dimension, named_set.hierarchy, named_set.level)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json;
+
+    use test_support::{exports, EXPORTS_JSON};
+
+    fn descriptions(cubes: Vec<CubeDescription>) -> CubeDescriptions {
+        CubeDescriptions { cubes }
+    }
+
+    #[test]
+    fn test_diff_same() {
+        let diff = SchemaDiff::new(&descriptions(vec![exports()]), &descriptions(vec![exports()]));
+
+        assert!(diff.changes.is_empty());
+        assert_eq!(diff.to_string(), "No differences\n");
+    }
+
+    #[test]
+    fn test_diff_cubes() {
+        let mut imports = exports();
+        imports.name = "imports".to_owned();
+
+        let diff = SchemaDiff::new(&descriptions(vec![exports()]), &descriptions(vec![imports]));
+
+        assert_eq!(diff.changes, vec![
+            Change::Removed { kind: "cube", path: "exports".to_owned() },
+            Change::Added { kind: "cube", path: "imports".to_owned() },
+        ]);
+    }
+
+    #[test]
+    fn test_diff_changes() {
+        let mut cube: serde_json::Value = serde_json::from_str(EXPORTS_JSON).unwrap();
+        {
+            cube["annotations"]["source"] = json_str("acs");
+            cube["annotations"]["updated"] = json_str("weekly");
+
+            // drop the Fiscal Year hierarchy, and a County property
+            let year = &mut cube["dimensions"][1]["hierarchies"];
+            year.as_array_mut().unwrap().pop();
+            let county = &mut cube["dimensions"][0]["hierarchies"][0]["levels"][2];
+            county["properties"] = serde_json::from_str(r#"["name_en", "name_fr"]"#).unwrap();
+            county["annotations"]["units"] = json_str("fips");
+
+            let measures = cube["measures"].as_array_mut().unwrap();
+            measures[0]["aggregator"] = json_str("AVG");
+            measures[1]["aggregator"] = serde_json::Value::Null;
+            measures.push(serde_json::from_str(
+                r#"{ "name": "Dollars Avg", "caption": "Dollars Avg", "annotations": {}, "full_name": "[Measures].[Dollars Avg]", "aggregator": "AVG" }"#
+            ).unwrap());
+
+            cube["named_sets"][0]["level"] = json_str("County");
+        }
+        let staging: CubeDescription = serde_json::from_value(cube).unwrap();
+
+        let diff = SchemaDiff::new(&descriptions(vec![exports()]), &descriptions(vec![staging]));
+
+        assert_eq!(
+            diff.to_string(),
+            "~ annotation exports / source: \"census\" -> \"acs\"\n\
+            + annotation exports / updated\n\
+            + annotation exports / Geography / Geography / County / units\n\
+            - property exports / Geography / Geography / County / name_es\n\
+            + property exports / Geography / Geography / County / name_fr\n\
+            - hierarchy exports / Year / Fiscal Year\n\
+            + measure exports / Dollars Avg\n\
+            ~ aggregator exports / Dollars Sum: SUM -> AVG\n\
+            ~ aggregator exports / Count: COUNT -> none\n\
+            ~ named set level exports / Big States: [Geography].[Geography].[State] -> [Geography].[Geography].[County]\n\
+            \n\
+            10 differences: 4 added, 2 removed, 4 changed\n"
+        );
+    }
+
+    fn json_str(s: &str) -> serde_json::Value {
+        serde_json::Value::String(s.to_owned())
+    }
+}
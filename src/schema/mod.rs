@@ -0,0 +1,602 @@
+/// structs for deserializing description of cube schema
+
+pub mod diff;
+
+use serde::{de, Deserialize, Deserializer};
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+use strsim::levenshtein;
+
+use api::names::LevelName;
+use api::parse::quote;
+
+#[derive(Debug, Deserialize)]
+pub struct CubeDescriptions {
+    pub cubes: Vec<CubeDescription>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CubeDescription {
+    pub name: String,
+    dimensions: Vec<Dimension>,
+    measures: Vec<Measure>,
+    named_sets: Vec<NamedSet>,
+    annotations: HashMap<String,String>,
+}
+
+impl fmt::Display for CubeDescription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut out = String::new();
+        out.push_str("Cube: ");
+        out.push_str(&self.name);
+        out.push_str("\n");
+
+        for (k, v) in &self.annotations {
+            out.push_str(format!("  ({}: {})\n", k, v).as_str());
+        }
+
+        out.push_str("  Dimensions, Hierarchies, and Levels:\n");
+        for dim in &self.dimensions {
+            for (k, v) in &dim.annotations {
+                out.push_str(format!("    [{}]::({}: {})\n", dim.name, k, v).as_str());
+            }
+            for hier in &dim.hierarchies {
+                // no hierarchy annotations for now
+                for lvl in &hier.levels[1..] {
+                    out.push_str("    ");
+                    out.push_str(&lvl.full_name);
+                    out.push_str("\n");
+
+                    for prop in &lvl.properties {
+                        out.push_str("      ");
+                        out.push_str(prop);
+                        out.push_str(" (property)\n");
+                    }
+
+                    for (k, v) in &lvl.annotations {
+                        out.push_str(format!("      ({}: {})\n", k, v).as_str());
+                    }
+                }
+            }
+        }
+
+        if !self.named_sets.is_empty() {
+            out.push_str("  Named Sets:\n");
+        }
+        for named_set in &self.named_sets {
+            for (k, v) in &named_set.annotations {
+                out.push_str(format!("    ({}: {})\n", k, v).as_str());
+            }
+
+            let level_name = LevelName::new(
+                named_set.dimension.to_owned(),
+                named_set.hierarchy.to_owned(),
+                named_set.level.to_owned());
+            out.push_str("    ");
+            out.push_str(&named_set.name);
+            out.push_str(": ");
+            out.push_str(level_name.to_string().as_str());
+            out.push_str("\n");
+        }
+
+        out.push_str("  Measures:\n");
+        for mea in &self.measures {
+            for (k, v) in &mea.annotations {
+                out.push_str(format!("    ({}: {})\n", k, v).as_str());
+            }
+
+            out.push_str("    ");
+            out.push_str(&mea.name);
+            if let Some(ref agg) = mea.aggregator {
+                out.push_str(" | agg: ");
+                out.push_str(&agg);
+            }
+            out.push_str("\n");
+        }
+
+        write!(f, "{}", out)
+    }
+}
+
+impl CubeDescription {
+    /// Short '.' delimited names of all levels below the
+    /// all level, e.g. Geography.County. The hierarchy is
+    /// only included when it differs from the dimension.
+    pub fn level_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+
+        for dim in &self.dimensions {
+            for hier in &dim.hierarchies {
+                let skip = if hier.has_all { 1 } else { 0 };
+                for lvl in hier.levels.iter().skip(skip) {
+                    names.push(short_level_name(&dim.name, &hier.name, &lvl.name));
+                }
+            }
+        }
+
+        names
+    }
+
+    pub fn named_set_names(&self) -> Vec<String> {
+        self.named_sets.iter().map(|set| set.name.clone()).collect()
+    }
+
+    pub fn measure_names(&self) -> Vec<String> {
+        self.measures.iter().map(|mea| mea.name.clone()).collect()
+    }
+
+    /// Short '.' delimited names of all properties,
+    /// e.g. Geography.County.name_en
+    pub fn property_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+
+        for dim in &self.dimensions {
+            for hier in &dim.hierarchies {
+                for lvl in &hier.levels {
+                    for prop in &lvl.properties {
+                        names.push(format!(
+                            "{}.{}",
+                            short_level_name(&dim.name, &hier.name, &lvl.name),
+                            quote(prop),
+                        ));
+                    }
+                }
+            }
+        }
+
+        names
+    }
+
+    /// Checks that a level exists in this cube. Otherwise returns
+    /// which part of the name is unknown, with a suggestion if there
+    /// is a close match.
+    pub fn check_level(&self, level_name: &LevelName) -> Result<(), String> {
+        self.find_level(level_name).map(|_| ())
+    }
+
+    pub fn check_measure(&self, measure: &str) -> Result<(), String> {
+        if self.measures.iter().any(|mea| mea.name == measure) {
+            Ok(())
+        } else {
+            Err(unknown_name(
+                "measure",
+                measure,
+                self.measures.iter().map(|mea| mea.name.as_str()),
+            ))
+        }
+    }
+
+    pub fn has_named_set(&self, named_set: &str) -> bool {
+        self.named_sets.iter().any(|set| set.name == named_set)
+    }
+
+    pub fn check_named_set(&self, named_set: &str) -> Result<(), String> {
+        if self.has_named_set(named_set) {
+            Ok(())
+        } else {
+            Err(unknown_name(
+                "named set",
+                named_set,
+                self.named_sets.iter().map(|set| set.name.as_str()),
+            ))
+        }
+    }
+
+    pub fn check_property(&self, level_name: &LevelName, property: &str) -> Result<(), String> {
+        let lvl = self.find_level(level_name)?;
+
+        if lvl.properties.iter().any(|prop| prop == property) {
+            Ok(())
+        } else {
+            Err(unknown_name(
+                "property",
+                property,
+                lvl.properties.iter().map(|prop| prop.as_str()),
+            ))
+        }
+    }
+
+    fn find_level(&self, level_name: &LevelName) -> Result<&Level, String> {
+        let dim = self.dimensions.iter()
+            .find(|dim| dim.name == level_name.dimension())
+            .ok_or_else(|| unknown_name(
+                "dimension",
+                level_name.dimension(),
+                self.dimensions.iter().map(|dim| dim.name.as_str()),
+            ))?;
+
+        let hier = dim.hierarchies.iter()
+            .find(|hier| hier.name == level_name.hierarchy())
+            .ok_or_else(|| unknown_name(
+                "hierarchy",
+                level_name.hierarchy(),
+                dim.hierarchies.iter().map(|hier| hier.name.as_str()),
+            ))?;
+
+        hier.levels.iter()
+            .find(|lvl| lvl.name == level_name.level())
+            .ok_or_else(|| unknown_name(
+                "level",
+                level_name.level(),
+                hier.levels.iter().map(|lvl| lvl.name.as_str()),
+            ))
+    }
+
+    /// Names to test with the quick strategy: the first level of
+    /// the default hierarchy of each dimension, and all properties,
+    /// by full name. (Thorough testing uses `level_names` and
+    /// `property_names` instead.)
+    pub fn test_drill_mea_prop(&self) -> Test {
+        let mut test_dims = Vec::new();
+
+        for dim in &self.dimensions {
+            let lvl = dim.hierarchies.first().and_then(|hier| hier.levels.first());
+            if let Some(lvl) = lvl {
+                test_dims.push(lvl.full_name.clone());
+            }
+        }
+
+        let mut test_props = Vec::new();
+
+        for dim in &self.dimensions {
+            for hier in &dim.hierarchies {
+                for level in &hier.levels {
+
+                    if !level.properties.is_empty() {
+                        let level_name = level.full_name.to_owned();
+                        let properties = level.properties.iter()
+                            .map(|prop| {
+                                let prop = format!(".[{}]", prop);
+                                let mut prop_full_name = level_name.clone();
+                                prop_full_name.push_str(&prop);
+                                prop_full_name
+                            });
+
+                        test_props.extend(properties);
+                    }
+
+                }
+            }
+        }
+
+        Test {
+            name: self.name.clone(),
+            dims: test_dims,
+            meas: self.measures.iter().map(|mea| mea.name.clone()).collect(),
+            props: test_props,
+            named_sets: self.named_set_names(),
+        }
+    }
+}
+
+fn unknown_name<'a, I>(kind: &str, name: &str, candidates: I) -> String
+    where I: Iterator<Item=&'a str>
+{
+    match did_you_mean(name, candidates) {
+        Some(suggestion) => format!("unknown {} {:?}; did you mean {:?}?", kind, name, suggestion),
+        None => format!("unknown {} {:?}", kind, name),
+    }
+}
+
+/// Closest candidate by edit distance (ignoring case),
+/// if it's close enough to be a likely typo.
+fn did_you_mean<'a, I>(name: &str, candidates: I) -> Option<&'a str>
+    where I: Iterator<Item=&'a str>
+{
+    let name_lower = name.to_lowercase();
+    let max_distance = cmp::max(2, name.chars().count() / 3);
+
+    candidates
+        .map(|candidate| (levenshtein(&name_lower, &candidate.to_lowercase()), candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+fn short_level_name(dimension: &str, hierarchy: &str, level: &str) -> String {
+    if dimension == hierarchy {
+        format!("{}.{}", quote(dimension), quote(level))
+    } else {
+        format!("{}.{}.{}", quote(dimension), quote(hierarchy), quote(level))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Dimension {
+    name: String,
+    caption: String,
+    annotations: HashMap<String,String>,
+    hierarchies: Vec<Hierarchy>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Hierarchy {
+    name: String,
+    has_all: bool,
+    all_member_name: String,
+    levels: Vec<Level>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Level {
+    name: String,
+    full_name: String,
+    depth: u32,
+    caption: String,
+    annotations: HashMap<String,String>,
+    properties: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Measure {
+    name: String,
+    caption: String,
+    annotations: HashMap<String,String>,
+    full_name: String,
+    aggregator: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NamedSet {
+    name: String,
+    dimension: String,
+    hierarchy: String,
+    level: String,
+    annotations: HashMap<String,String>,
+}
+
+#[derive(Debug)]
+pub struct Test {
+    pub name: String,
+    pub dims: Vec<String>,
+    pub meas: Vec<String>,
+    pub props: Vec<String>,
+    pub named_sets: Vec<String>,
+}
+
+
+impl fmt::Display for Test {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut out = String::new();
+        out.push_str("Test Cube: ");
+        out.push_str(&self.name.clone());
+        out.push_str("\n");
+
+        out.push_str("  Dimensions, Hierarchies, and Levels:\n");
+        for dim in &self.dims {
+            out.push_str("    ");
+            out.push_str(&dim);
+            out.push_str("\n");
+        }
+
+        out.push_str("  Measures:\n");
+        for mea in &self.meas {
+            out.push_str("    ");
+            out.push_str(&mea);
+            out.push_str("\n");
+        }
+
+        out.push_str("  Properties:\n");
+        for prop in &self.props {
+            out.push_str("    ");
+            out.push_str(&prop);
+            out.push_str("\n");
+        }
+
+        out.push_str("  Named Sets:\n");
+        for named_set in &self.named_sets {
+            out.push_str("    ");
+            out.push_str(&named_set);
+            out.push_str("\n");
+        }
+
+        write!(f, "{}", out)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Members {
+    name: String,
+    caption: String,
+    members: Vec<Member>,
+}
+
+impl Members {
+    /// Keys of the first and last member, for sample cuts.
+    /// Only one key if there's only one member.
+    pub fn sample_keys(&self) -> Vec<&Key> {
+        let mut keys = Vec::new();
+        if let Some(first) = self.members.first() {
+            keys.push(&first.key);
+        }
+        if self.members.len() > 1 {
+            if let Some(last) = self.members.last() {
+                keys.push(&last.key);
+            }
+        }
+        keys
+    }
+}
+
+impl fmt::Display for Members {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut out = String::new();
+        out.push_str(format!("Members of Level {}:\n", self.name).as_str());
+
+        for member in &self.members {
+            out.push_str(member.to_string().as_str());
+        }
+
+        write!(f, "{}", out)
+    }
+}
+
+
+#[derive(Debug, Deserialize)]
+pub struct Member {
+    name: String,
+    full_name: String,
+    caption: String,
+    key: Key, // always string because it is sometimes str sometimes num
+    #[serde(rename = "all_member?")]
+    is_all_member: bool,
+    #[serde(rename = "drillable?")]
+    is_drillable: bool,
+    depth: u32,
+    num_children: u32,
+    parent_name: String,
+    level_name: String,
+    children: Vec<String>, // should be Vec of children, but don't need now
+}
+
+impl fmt::Display for Member {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut out = String::new();
+        out.push_str(format!("&{}: {}\n", self.key, self.name).as_str());
+
+        write!(f, "{}", out)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Key {
+    String(String),
+    Int(i64),
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Key::String(ref s) => write!(f, "{}", s),
+            Key::Int(ref i) => write!(f, "{}", i),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json;
+
+    use api::{self, Drilldown, Measure, Cut, Property};
+    use test_support::exports;
+
+    #[test]
+    fn test_names() {
+        let cube = exports();
+
+        assert_eq!(cube.level_names(), vec![
+            "Geography.State",
+            "Geography.County",
+            "Year.Year",
+            "Year.Fiscal Year.Fiscal Year",
+        ]);
+        assert_eq!(cube.measure_names(), vec!["Dollars Sum", "Count"]);
+        assert_eq!(cube.property_names(), vec![
+            "Geography.County.name_en",
+            "Geography.County.name_es",
+        ]);
+    }
+
+    #[test]
+    fn test_drill_mea_prop() {
+        let cube = exports();
+
+        let quick = cube.test_drill_mea_prop();
+        assert_eq!(quick.dims, vec!["[Geography].[(All)]", "[Year].[(All)]"]);
+        assert_eq!(quick.meas, vec!["Dollars Sum", "Count"]);
+        assert_eq!(quick.props, vec!["[Geography].[County].[name_en]", "[Geography].[County].[name_es]"]);
+        assert_eq!(quick.named_sets, vec!["Big States"]);
+
+        // all test names must parse, including those for thorough testing
+        for dim in quick.dims.iter().chain(&cube.level_names()) {
+            assert!(dim.parse::<Drilldown>().is_ok(), "{}", dim);
+        }
+        for prop in quick.props.iter().chain(&cube.property_names()) {
+            assert!(prop.parse::<Property>().is_ok(), "{}", prop);
+        }
+    }
+
+    #[test]
+    fn test_members_sample_keys() {
+        let member = |key: &str| format!(r#"{{
+            "name": "m", "full_name": "[Year].[m]", "caption": "m",
+            "key": {}, "all_member?": false, "drillable?": false, "depth": 1,
+            "num_children": 0, "parent_name": "All Years", "level_name": "Year", "children": []
+        }}"#, key);
+        let members = |keys: &[&str]| -> Members {
+            let members = keys.iter().map(|key| member(key)).collect::<Vec<_>>().join(",");
+            serde_json::from_str(&format!(
+                r#"{{ "name": "Year", "caption": "Year", "members": [{}] }}"#, members
+            )).unwrap()
+        };
+
+        let keys = |members: &Members| {
+            members.sample_keys().iter().map(|key| key.to_string()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(keys(&members(&["2014", "2015", "2016"])), vec!["2014", "2016"]);
+        assert_eq!(keys(&members(&[r#""CA""#, r#""NY""#])), vec!["CA", "NY"]);
+        assert_eq!(keys(&members(&["1"])), vec!["1"]);
+        assert!(keys(&members(&[])).is_empty());
+    }
+
+    #[test]
+    fn test_check_names() {
+        let cube = exports();
+
+        assert!(cube.check_level(&"Geography.County".parse().unwrap()).is_ok());
+        assert!(cube.check_level(&"Year.Fiscal Year.Fiscal Year".parse().unwrap()).is_ok());
+        assert!(cube.check_measure("Dollars Sum").is_ok());
+        assert!(cube.check_named_set("Big States").is_ok());
+        assert!(cube.check_property(&"Geography.County".parse().unwrap(), "name_en").is_ok());
+
+        assert_eq!(
+            cube.check_level(&"Geograpy.County".parse().unwrap()),
+            Err("unknown dimension \"Geograpy\"; did you mean \"Geography\"?".to_owned())
+        );
+        assert_eq!(
+            cube.check_level(&"Geography.Country".parse().unwrap()),
+            Err("unknown level \"Country\"; did you mean \"County\"?".to_owned())
+        );
+        assert_eq!(
+            cube.check_measure("dollars sum"),
+            Err("unknown measure \"dollars sum\"; did you mean \"Dollars Sum\"?".to_owned())
+        );
+        assert_eq!(
+            cube.check_named_set("Big State"),
+            Err("unknown named set \"Big State\"; did you mean \"Big States\"?".to_owned())
+        );
+        assert_eq!(
+            cube.check_measure("Revenue"),
+            Err("unknown measure \"Revenue\"".to_owned())
+        );
+        assert_eq!(
+            cube.check_property(&"Geography.County".parse().unwrap(), "name_fr"),
+            Err("unknown property \"name_fr\"; did you mean \"name_en\"?".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_validate_query() {
+        let cube = exports();
+
+        let mut req = api::query("http://localhost:5000/".to_owned());
+        req.cube("exports")
+            .drilldown(Drilldown::new("Geography", "Geography", "County"))
+            .measure(Measure::new("Dollars Sum"))
+            .cut(Cut::new("Year", "Year", "Year", vec!["2016"]))
+            .property(Property::new("Geography", "Geography", "County", "name_en"));
+
+        assert!(req.validate(&cube).is_ok());
+
+        req.drilldown(Drilldown::new("Year", "Year", "Yaer"))
+            .measure(Measure::new("Cuont"));
+
+        let err = req.validate(&cube).unwrap_err().to_string();
+        assert!(err.contains("drilldown [Year].[Year].[Yaer]: unknown level \"Yaer\"; did you mean \"Year\"?"));
+        assert!(err.contains("measure Cuont: unknown measure \"Cuont\"; did you mean \"Count\"?"));
+    }
+}
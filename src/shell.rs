@@ -0,0 +1,384 @@
+//! Interactive shell for building a query step by step.
+//!
+//! Cube descriptions are fetched once at startup and used
+//! for tab completion of cube, level, measure, and property
+//! names.
+
+use failure::Error;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use serde_json;
+
+use mondrian_rest::aggregate::Aggregation;
+use mondrian_rest::api::{QueryBuilder, ResponseFormat};
+use mondrian_rest::schema::CubeDescription;
+use mondrian_rest::Client;
+
+const COMMANDS: &[&str] = &[
+    "use",
+    "drill",
+    "measure",
+    "cut",
+    "property",
+    "flag",
+    "format",
+    "show",
+    "run",
+    "clear",
+    "cubes",
+    "describe",
+    "help",
+    "quit",
+];
+
+const FORMATS: &[&str] = &["json", "jsonrecords", "csv", "table"];
+
+const FLAGS: &[&str] = &["debug", "parents", "nonempty", "distinct", "sparse"];
+
+const HELP: &str = "\
+Commands:
+    use <cube>              start a new query on cube
+    drill <level>           add drilldown, e.g. Geography.County
+    measure <measure>       add measure, e.g. \"Dollars Sum\"
+    cut <level>.<members>   add cut, e.g. Year.Year.2015,2016
+    property <property>     add property, e.g. Geography.County.name_en
+    flag <flag> [on|off]    debug, parents, nonempty, distinct, or sparse
+    format <format>         json, jsonrecords, csv, or table
+    show                    print the query url
+    run                     run the query
+    clear                   start over on the current cube
+    cubes                   list cubes
+    describe [cube]         describe current or specified cube
+    help                    print this message
+    quit                    exit the shell
+
+Names can be quoted; tab completes commands and names from the schema.";
+
+struct ShellHelper {
+    cubes: Vec<CubeDescription>,
+    current: Option<usize>,
+}
+
+impl ShellHelper {
+    fn cube(&self, cube_name: &str) -> Option<&CubeDescription> {
+        self.cubes.iter().find(|cube| cube.name == cube_name)
+    }
+
+    fn current(&self) -> Option<&CubeDescription> {
+        self.current.map(|i| &self.cubes[i])
+    }
+
+    fn candidates(&self, cmd: &str) -> Vec<String> {
+        let cube = self.current();
+
+        match cmd {
+            "use" | "describe" => self.cubes.iter().map(|cube| cube.name.clone()).collect(),
+            "drill" => cube.map(|cube| cube.level_names()).unwrap_or_default(),
+            "cut" => {
+                cube.map(|cube| {
+                    cube.level_names().into_iter()
+                        .map(|mut lvl| { lvl.push('.'); lvl })
+                        .collect()
+                })
+                .unwrap_or_default()
+            },
+            "measure" => cube.map(|cube| cube.measure_names()).unwrap_or_default(),
+            "property" => cube.map(|cube| cube.property_names()).unwrap_or_default(),
+            "format" => FORMATS.iter().map(|s| s.to_string()).collect(),
+            "flag" => FLAGS.iter().map(|s| s.to_string()).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = String;
+
+    /// Completes the command when there's no space yet; otherwise
+    /// the whole rest of the line is the name being completed,
+    /// since names may contain spaces.
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context) -> Result<(usize, Vec<String>), ReadlineError> {
+        let line = &line[..pos];
+        let trimmed = line.trim_start();
+        let offset = line.len() - trimmed.len();
+
+        let cmd_end = match trimmed.find(' ') {
+            Some(idx) => idx,
+            None => {
+                let candidates = COMMANDS.iter()
+                    .filter(|cmd| cmd.starts_with(trimmed))
+                    .map(|cmd| format!("{} ", cmd))
+                    .collect();
+                return Ok((offset, candidates));
+            },
+        };
+
+        let cmd = &trimmed[..cmd_end];
+        let rest = &trimmed[cmd_end..];
+        let mut start = offset + cmd_end + (rest.len() - rest.trim_start().len());
+        let mut arg = rest.trim_start();
+
+        if arg.starts_with('"') {
+            start += 1;
+            arg = &arg[1..];
+        }
+
+        let candidates = self.candidates(cmd).into_iter()
+            .filter(|name| name.starts_with(arg))
+            .collect();
+
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+struct Shell<'a> {
+    client: &'a Client,
+    verbose: bool,
+    editor: Editor<ShellHelper>,
+    query: Option<QueryBuilder>,
+}
+
+impl<'a> Shell<'a> {
+    fn helper(&self) -> &ShellHelper {
+        // helper is always set on init
+        self.editor.helper().unwrap()
+    }
+
+    fn query(&mut self) -> Result<&mut QueryBuilder, Error> {
+        self.query.as_mut().ok_or_else(|| format_err!("No cube selected; try `use <cube>`"))
+    }
+
+    fn new_query(&mut self, cube_name: &str) {
+        let mut req = self.client.query();
+        req.cube(cube_name)
+            .format(ResponseFormat::Table);
+        self.query = Some(req);
+    }
+
+    /// Returns output to print, if any
+    fn eval(&mut self, cmd: &str, arg: &str) -> Result<Option<String>, Error> {
+        match cmd {
+            "use" => {
+                let idx = self.helper().cubes.iter()
+                    .position(|cube| cube.name == arg)
+                    .ok_or_else(|| format_err!("Cube {:?} not found", arg))?;
+                self.editor.helper_mut().unwrap().current = Some(idx);
+                self.new_query(arg);
+            },
+            "clear" => {
+                let cube_name = self.helper().current()
+                    .map(|cube| cube.name.clone())
+                    .ok_or_else(|| format_err!("No cube selected; try `use <cube>`"))?;
+                self.new_query(&cube_name);
+            },
+            "drill" => { self.query()?.drilldown(arg.parse()?); },
+            "measure" => { self.query()?.measure(arg.parse()?); },
+            "cut" => { self.query()?.cut(arg.parse()?); },
+            "property" => { self.query()?.property(arg.parse()?); },
+            "format" => { self.query()?.format(arg.parse()?); },
+            "flag" => {
+                let mut parts = arg.split_whitespace();
+                let flag = parts.next().unwrap_or("");
+                let on = match parts.next() {
+                    None | Some("on") => true,
+                    Some("off") => false,
+                    Some(other) => bail!("Expected on or off, found {:?}", other),
+                };
+
+                let req = self.query()?;
+                match flag {
+                    "debug" => { req.debug(on); },
+                    "parents" => { req.parents(on); },
+                    "nonempty" => { req.nonempty(on); },
+                    "distinct" => { req.distinct(on); },
+                    "sparse" => { req.sparse(on); },
+                    _ => bail!("Unknown flag {:?}; expected one of {}", flag, FLAGS.join(", ")),
+                }
+            },
+            "show" => {
+                return Ok(Some(self.query()?.url()?.to_string()));
+            },
+            "run" => {
+                return self.run().map(Some);
+            },
+            "cubes" => {
+                let names = self.helper().cubes.iter()
+                    .map(|cube| cube.name.clone())
+                    .collect::<Vec<_>>();
+                return Ok(Some(names.join("\n")));
+            },
+            "describe" => {
+                let helper = self.helper();
+                let cube = if arg.is_empty() {
+                    helper.current().ok_or_else(|| format_err!("No cube selected; try `use <cube>`"))?
+                } else {
+                    helper.cube(arg).ok_or_else(|| format_err!("Cube {:?} not found", arg))?
+                };
+                return Ok(Some(cube.to_string()));
+            },
+            "help" => {
+                return Ok(Some(HELP.to_owned()));
+            },
+            _ => bail!("Unknown command {:?}; try `help`", cmd),
+        }
+
+        Ok(None)
+    }
+
+    fn run(&mut self) -> Result<String, Error> {
+        let client = self.client;
+        let verbose = self.verbose;
+        let req = self.query.as_ref().ok_or_else(|| format_err!("No cube selected; try `use <cube>`"))?;
+
+        // schema is already fetched, so always validate
+        if let Some(cube) = self.editor.helper().and_then(|helper| helper.current()) {
+            req.validate(cube)?;
+        }
+
+        let url = req.url()?;
+        ensure!(
+            url.path().contains("/aggregate."),
+            "Query needs at least one drilldown and one measure"
+        );
+        if verbose {
+            println!("{}", url);
+        }
+
+        let resp = client.aggregate(req)?;
+
+        if *req.response_format() == ResponseFormat::Table {
+            let agg: Aggregation = serde_json::from_str(&resp)?;
+            Ok(agg.to_string())
+        } else {
+            Ok(resp)
+        }
+    }
+}
+
+/// Splits a line into command and argument. The argument
+/// is the rest of the line, with surrounding quotes removed.
+fn split_line(line: &str) -> (&str, &str) {
+    let line = line.trim();
+    match line.find(' ') {
+        Some(idx) => (&line[..idx], line[idx..].trim().trim_matches('"')),
+        None => (line, ""),
+    }
+}
+
+pub fn run(client: &Client, verbose: bool) -> Result<(), Error> {
+    if verbose {
+        println!("{}", client.query().url()?);
+    }
+    let cubes = client.cubes()?;
+
+    let mut editor = Editor::<ShellHelper>::new();
+    editor.set_helper(Some(ShellHelper {
+        cubes: cubes.cubes,
+        current: None,
+    }));
+
+    let mut shell = Shell {
+        client,
+        verbose,
+        editor,
+        query: None,
+    };
+
+    println!("Type `help` for commands, tab to complete");
+
+    loop {
+        let prompt = match shell.helper().current() {
+            Some(cube) => format!("{}> ", cube.name),
+            None => "> ".to_owned(),
+        };
+
+        let line = match shell.editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+
+        let (cmd, arg) = split_line(&line);
+        if cmd.is_empty() {
+            continue;
+        }
+        shell.editor.add_history_entry(line.as_str());
+
+        if cmd == "quit" || cmd == "exit" {
+            break;
+        }
+
+        match shell.eval(cmd, arg) {
+            Ok(Some(out)) => println!("{}", out),
+            Ok(None) => (),
+            Err(err) => {
+                for cause in err.causes() {
+                    println!("{}", cause);
+                }
+            },
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rustyline::history::History;
+    use mondrian_rest::test_support::exports;
+
+    fn complete(helper: &ShellHelper, line: &str) -> (usize, Vec<String>) {
+        let history = History::new();
+        helper.complete(line, line.len(), &Context::new(&history)).unwrap()
+    }
+
+    #[test]
+    fn test_complete() {
+        let mut helper = ShellHelper { cubes: vec![exports()], current: None };
+
+        assert_eq!(complete(&helper, "dr"), (0, vec!["drill ".to_owned()]));
+        assert_eq!(complete(&helper, "use ex"), (4, vec!["exports".to_owned()]));
+        // names need a cube
+        assert_eq!(complete(&helper, "drill Geo"), (6, Vec::new()));
+
+        helper.current = Some(0);
+
+        // dimension, then level
+        assert_eq!(complete(&helper, "drill Geo"), (6, vec!["Geography.State".to_owned(), "Geography.County".to_owned()]));
+        assert_eq!(complete(&helper, "drill Geography.C"), (6, vec!["Geography.County".to_owned()]));
+        // a quoted name is completed after the quote
+        assert_eq!(complete(&helper, "drill \"Year.F"), (7, vec!["Year.Fiscal Year.Fiscal Year".to_owned()]));
+        assert_eq!(complete(&helper, "cut Year.Y"), (4, vec!["Year.Year.".to_owned()]));
+
+        assert_eq!(complete(&helper, "measure "), (8, vec!["Dollars Sum".to_owned(), "Count".to_owned()]));
+        assert_eq!(complete(&helper, "measure \"Dollars S"), (9, vec!["Dollars Sum".to_owned()]));
+        assert_eq!(complete(&helper, "property Geography.County.name_e"), (9, vec![
+            "Geography.County.name_en".to_owned(),
+            "Geography.County.name_es".to_owned(),
+        ]));
+        assert_eq!(complete(&helper, "format cs"), (7, vec!["csv".to_owned()]));
+    }
+
+    #[test]
+    fn test_split_line() {
+        assert_eq!(split_line("use exports"), ("use", "exports"));
+        assert_eq!(split_line("  measure \"Dollars Sum\" "), ("measure", "Dollars Sum"));
+        assert_eq!(split_line("run"), ("run", ""));
+    }
+}
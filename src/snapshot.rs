@@ -0,0 +1,384 @@
+//! Snapshot testing of query results.
+//!
+//! `record` runs each query of a query file (see spec module) and
+//! saves the query together with its response, one json file per
+//! query in the snapshot directory. `verify` re-runs the saved
+//! queries and compares the new responses with the saved ones.
+//!
+//! Responses are compared as aggregations (see compare module),
+//! so key order in the json and row order don't matter.
+
+use failure::Error;
+use serde_json::{self, Value};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use compare::{Comparison, Tolerance};
+use mondrian_rest::aggregate::Aggregation;
+use mondrian_rest::api::ResponseFormat;
+use mondrian_rest::error::CliError;
+use mondrian_rest::Client;
+use spec::QuerySpec;
+use testing::format_error;
+
+pub const DEFAULT_DIR: &str = "snapshots";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub query: QuerySpec,
+    /// Url the response was recorded from, for reference only
+    pub url: String,
+    pub response: Value,
+}
+
+/// Snapshot files written and removed by `record`
+#[derive(Debug)]
+pub struct Recorded {
+    pub paths: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+}
+
+/// Runs the queries and writes their snapshots, stopping at the
+/// first error so that a snapshot is never recorded from a
+/// failed query.
+///
+/// Once all queries are recorded, other snapshots in the directory
+/// (e.g. of a query since renamed or removed from the query file)
+/// are removed, so that `verify` doesn't run them.
+pub fn record(client: &Client,
+    queries: &[QuerySpec],
+    dir: &Path,
+    verbose: bool,
+    ) -> Result<Recorded, Error>
+{
+    fs::create_dir_all(dir)
+        .map_err(|err| CliError::Config(format!("Could not create snapshot dir {:?}: {}", dir, err)))?;
+
+    let mut paths = Vec::new();
+
+    for query in queries {
+        let path = dir.join(file_name(&query.name));
+        if paths.contains(&path) {
+            return Err(CliError::Config(format!(
+                "Query {:?} has the same snapshot file name as another query: {:?}",
+                query.name, path,
+            )).into());
+        }
+
+        let url = query.query_builder(client.base_url())?
+            .format(ResponseFormat::Json)
+            .url()?;
+        if verbose {
+            println!("{}", url);
+        }
+
+        let resp = client.get(url.clone())
+            .map_err(|err| err.context(format!("Query {:?} failed", query.name)))?;
+
+        // check that the response can be compared later
+        let response: Value = serde_json::from_str(&resp)?;
+        serde_json::from_value::<Aggregation>(response.clone())?;
+
+        let snapshot = Snapshot {
+            query: query.clone(),
+            url: url.to_string(),
+            response,
+        };
+        fs::write(&path, serde_json::to_string_pretty(&snapshot)?)?;
+
+        paths.push(path);
+    }
+
+    let mut removed = Vec::new();
+    for path in json_paths(dir)? {
+        if !paths.contains(&path) {
+            fs::remove_file(&path)
+                .map_err(|err| CliError::Config(format!("Could not remove old snapshot {:?}: {}", path, err)))?;
+            removed.push(path);
+        }
+    }
+
+    Ok(Recorded { paths, removed })
+}
+
+/// Outcome of verifying one snapshot
+#[derive(Debug)]
+pub enum Verified {
+    Unchanged,
+    Changed(Comparison),
+    Failed(String),
+}
+
+#[derive(Debug)]
+pub struct VerifyResult {
+    pub name: String,
+    pub verified: Verified,
+}
+
+/// Re-runs every snapshot in the directory, in file name order.
+/// A failing query doesn't stop the run.
+pub fn verify(client: &Client,
+    dir: &Path,
+    tolerance: &Tolerance,
+    verbose: bool,
+    ) -> Result<Vec<VerifyResult>, Error>
+{
+    let paths = snapshot_paths(dir)?;
+
+    let results = paths.iter()
+        .map(|path| {
+            let snapshot = match load(path) {
+                Ok(snapshot) => snapshot,
+                Err(err) => {
+                    return VerifyResult {
+                        name: path.display().to_string(),
+                        verified: Verified::Failed(format!("Invalid snapshot: {}", err)),
+                    };
+                },
+            };
+
+            let verified = match verify_one(client, &snapshot, tolerance, verbose) {
+                Ok(comparison) => {
+                    if comparison.is_match() {
+                        Verified::Unchanged
+                    } else {
+                        Verified::Changed(comparison)
+                    }
+                },
+                Err(err) => Verified::Failed(format_error(&err)),
+            };
+
+            VerifyResult { name: snapshot.query.name, verified }
+        })
+        .collect();
+
+    Ok(results)
+}
+
+fn verify_one(client: &Client,
+    snapshot: &Snapshot,
+    tolerance: &Tolerance,
+    verbose: bool,
+    ) -> Result<Comparison, Error>
+{
+    let expected: Aggregation = serde_json::from_value(snapshot.response.clone())?;
+
+    let url = snapshot.query.query_builder(client.base_url())?
+        .format(ResponseFormat::Json)
+        .url()?;
+    if verbose {
+        println!("{}", url);
+    }
+    let actual: Aggregation = serde_json::from_str(&client.get(url)?)?;
+
+    let mut comparison = Comparison::new(&expected, &actual, tolerance);
+    comparison.labels = ("snapshot", "current");
+    Ok(comparison)
+}
+
+/// Snapshot files in the directory, sorted by path
+fn snapshot_paths(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let paths = json_paths(dir)?;
+
+    if paths.is_empty() {
+        return Err(CliError::Config(format!("No snapshots found in {:?}", dir)).into());
+    }
+
+    Ok(paths)
+}
+
+/// All json files in the directory, sorted
+fn json_paths(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let entries = fs::read_dir(dir)
+        .map_err(|err| CliError::Config(format!("Could not read snapshot dir {:?}: {}", dir, err)))?;
+
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().map(|ext| ext == "json").unwrap_or(false) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    Ok(paths)
+}
+
+fn load(path: &Path) -> Result<Snapshot, Error> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// File name from the query name: lowercase alphanumerics,
+/// with everything else collapsed to '-'
+fn file_name(query_name: &str) -> String {
+    let mut name = String::new();
+    for c in query_name.chars() {
+        if c.is_alphanumeric() {
+            name.extend(c.to_lowercase());
+        } else if !name.is_empty() && !name.ends_with('-') {
+            name.push('-');
+        }
+    }
+    let name = name.trim_end_matches('-');
+
+    format!("{}.json", if name.is_empty() { "query" } else { name })
+}
+
+pub struct VerifySummary<'a> {
+    pub results: &'a [VerifyResult],
+}
+
+impl<'a> VerifySummary<'a> {
+    /// Snapshots which changed or couldn't be verified
+    pub fn failed(&self) -> usize {
+        self.results.iter()
+            .filter(|res| !matches!(res.verified, Verified::Unchanged))
+            .count()
+    }
+}
+
+impl<'a> fmt::Display for VerifySummary<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut changed = 0;
+        let mut errors = 0;
+
+        for res in self.results {
+            match res.verified {
+                Verified::Unchanged => writeln!(f, "ok       {}", res.name)?,
+                Verified::Changed(ref comparison) => {
+                    changed += 1;
+                    writeln!(f, "CHANGED  {}", res.name)?;
+                    for line in comparison.to_string().lines() {
+                        writeln!(f, "    {}", line)?;
+                    }
+                },
+                Verified::Failed(ref msg) => {
+                    errors += 1;
+                    writeln!(f, "FAILED   {}", res.name)?;
+                    for line in msg.lines() {
+                        writeln!(f, "    {}", line)?;
+                    }
+                },
+            }
+        }
+
+        write!(f, "\nSnapshots: {} verified, {} changed, {} failed", self.results.len(), changed, errors)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mondrian_rest::test_support::{fixture_dir, replay};
+
+    const COUNT_BY_COUNTY: &str = r#"{
+        "axes": [
+            { "members": [{ "name": "Count", "full_name": "[Measures].[Count]", "caption": "Count" }] },
+            { "members": [{ "name": "Adams", "full_name": "[Geography].[Adams]", "caption": "Adams" }] }
+        ],
+        "axis_dimensions": [
+            { "name": "Measures", "caption": "Measures" },
+            { "name": "Geography", "caption": "Geography" }
+        ],
+        "values": [[3]]
+    }"#;
+
+    fn query(name: &str) -> QuerySpec {
+        QuerySpec {
+            name: name.to_owned(),
+            cube: "exports".to_owned(),
+            drilldowns: vec!["Geography.County".to_owned()],
+            named_sets: Vec::new(),
+            measures: vec!["Count".to_owned()],
+            cuts: Vec::new(),
+            properties: Vec::new(),
+            parents: false,
+            nonempty: false,
+            distinct: false,
+            sparse: false,
+            format: None,
+            output: None,
+        }
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("exports by county"), "exports-by-county.json");
+        assert_eq!(file_name("  Exports / County (2016)! "), "exports-county-2016.json");
+        assert_eq!(file_name("???"), "query.json");
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let snapshot = Snapshot {
+            query: query("count by county"),
+            url: "http://localhost:5000/cubes/exports/aggregate.json".to_owned(),
+            response: serde_json::from_str(COUNT_BY_COUNTY).unwrap(),
+        };
+
+        let saved = serde_json::to_string_pretty(&snapshot).unwrap();
+        let loaded: Snapshot = serde_json::from_str(&saved).unwrap();
+        assert_eq!(loaded.query, snapshot.query);
+
+        // re-ordered keys in the new response
+        let actual = r#"{
+            "values": [[3]],
+            "axis_dimensions": [
+                { "caption": "Measures", "name": "Measures" },
+                { "caption": "Geography", "name": "Geography" }
+            ],
+            "axes": [
+                { "members": [{ "caption": "Count", "full_name": "[Measures].[Count]", "name": "Count" }] },
+                { "members": [{ "caption": "Adams", "full_name": "[Geography].[Adams]", "name": "Adams" }] }
+            ]
+        }"#;
+        let expected: Aggregation = serde_json::from_value(loaded.response).unwrap();
+        let actual: Aggregation = serde_json::from_str(actual).unwrap();
+        assert!(Comparison::new(&expected, &actual, &Tolerance::default()).is_match());
+    }
+
+    #[test]
+    fn test_record_removes_old_snapshots() {
+        let base_url = "http://localhost:5000";
+        let dir = fixture_dir("snapshot-record-dir");
+        fs::create_dir_all(&dir).unwrap();
+
+        // recorded before the query was renamed
+        fs::write(dir.join("count-by-county.json"), "{}").unwrap();
+        fs::write(dir.join("notes.txt"), "not a snapshot").unwrap();
+
+        let queries = vec![query("county counts")];
+        let url = queries[0].query_builder(base_url).unwrap()
+            .format(ResponseFormat::Json)
+            .url().unwrap()
+            .to_string();
+        let client = Client::with_transport(replay("snapshot-record", &[(&url, 200, COUNT_BY_COUNTY)]), base_url);
+
+        let recorded = record(&client, &queries, &dir, false).unwrap();
+        assert_eq!(recorded.paths, vec![dir.join("county-counts.json")]);
+        assert_eq!(recorded.removed, vec![dir.join("count-by-county.json")]);
+
+        assert_eq!(snapshot_paths(&dir).unwrap(), recorded.paths);
+        assert!(dir.join("notes.txt").exists());
+    }
+
+    #[test]
+    fn test_verify_summary() {
+        let results = vec![
+            VerifyResult { name: "a".to_owned(), verified: Verified::Unchanged },
+            VerifyResult { name: "b".to_owned(), verified: Verified::Failed("[500] Mondrian error".to_owned()) },
+        ];
+        let summary = VerifySummary { results: &results };
+
+        assert_eq!(summary.failed(), 1);
+        assert_eq!(
+            summary.to_string(),
+            "ok       a\n\
+            FAILED   b\n\
+            \x20   [500] Mondrian error\n\
+            \n\
+            Snapshots: 2 verified, 0 changed, 1 failed"
+        );
+    }
+}
@@ -0,0 +1,353 @@
+//! Queries written in a toml file, for running a set of queries
+//! at once (e.g. snapshot testing, or batch extracts).
+//!
+//! ```toml
+//! [[query]]
+//! name = "exports by county"
+//! cube = "exports"
+//! drilldowns = ["Geography.County", "Year.Year"]
+//! measures = ["Dollars Sum"]
+//! cuts = ["Year.Year.2016"]
+//! parents = true
+//! ```
+//!
+//! Names use the same syntax as on the command line. Like the query
+//! subcommand, a query needs a drilldown (or named set) and a measure.
+//!
+//! `format` and `output` are only used by batch; snapshots are
+//! always json.
+//!
+//! A single query can also be saved as a `QueryBuilder` (see its
+//! docs for the schema), in json or toml, with `query --save`.
+
+use failure::Error;
+use serde_json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml;
+
+use mondrian_rest::api::{self, QueryBuilder, ResponseFormat};
+use mondrian_rest::error::CliError;
+
+#[derive(Debug, Deserialize)]
+struct QueryFile {
+    #[serde(rename = "query", default)]
+    queries: Vec<QuerySpec>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuerySpec {
+    pub name: String,
+    pub cube: String,
+    #[serde(default)]
+    pub drilldowns: Vec<String>,
+    #[serde(default)]
+    pub named_sets: Vec<String>,
+    pub measures: Vec<String>,
+    #[serde(default)]
+    pub cuts: Vec<String>,
+    #[serde(default)]
+    pub properties: Vec<String>,
+    #[serde(default)]
+    pub parents: bool,
+    #[serde(default)]
+    pub nonempty: bool,
+    #[serde(default)]
+    pub distinct: bool,
+    #[serde(default)]
+    pub sparse: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+}
+
+impl QuerySpec {
+    /// Parses the names, so that a typo is reported with the
+    /// name of the query it's in.
+    pub fn query_builder(&self, base_url: &str) -> Result<QueryBuilder, Error> {
+        let mut req = api::query(base_url.to_owned());
+        req.cube(self.cube.clone())
+            .drilldowns(self.parse_names(&self.drilldowns)?)
+            .named_sets(self.named_sets.clone())
+            .measures(self.parse_names(&self.measures)?)
+            .cuts(self.parse_names(&self.cuts)?)
+            .properties(self.parse_names(&self.properties)?)
+            .parents(self.parents)
+            .nonempty(self.nonempty)
+            .distinct(self.distinct)
+            .sparse(self.sparse);
+
+        if let Some(ref format) = self.format {
+            let format: ResponseFormat = format.parse()
+                .map_err(|err: Error| CliError::Config(format!("In query {:?}: {}", self.name, err)))?;
+            req.format(format);
+        }
+
+        Ok(req)
+    }
+
+    fn parse_names<T>(&self, names: &[String]) -> Result<Vec<T>, Error>
+        where T: ::std::str::FromStr<Err=Error>,
+    {
+        names.iter()
+            .map(|s| {
+                s.parse().map_err(|err: Error| {
+                    CliError::Name(format!("In query {:?}: {}", self.name, err)).into()
+                })
+            })
+            .collect()
+    }
+}
+
+/// Reads the queries of a query file. Query names must be
+/// unique, since they're used to name output files.
+pub fn load(path: &Path) -> Result<Vec<QuerySpec>, Error> {
+    let s = fs::read_to_string(path)
+        .map_err(|err| CliError::Config(format!("Could not read query file {:?}: {}", path, err)))?;
+
+    parse(&s)
+        .map_err(|err| CliError::Config(format!("Invalid query file {:?}: {}", path, err)).into())
+}
+
+fn parse(s: &str) -> Result<Vec<QuerySpec>, Error> {
+    let file: QueryFile = toml::from_str(s)?;
+
+    if file.queries.is_empty() {
+        bail!("no [[query]] entries");
+    }
+
+    let mut names: Vec<&str> = Vec::new();
+    for query in &file.queries {
+        if query.name.trim().is_empty() {
+            bail!("query name can't be empty");
+        }
+        if names.contains(&query.name.as_str()) {
+            bail!("query name {:?} is used more than once", query.name);
+        }
+        if (query.drilldowns.is_empty() && query.named_sets.is_empty()) || query.measures.is_empty() {
+            bail!("query {:?} needs a drilldown (or named set) and a measure", query.name);
+        }
+        names.push(&query.name);
+    }
+
+    Ok(file.queries)
+}
+
+/// Reads a query saved with `save_query`
+pub fn load_query(path: &Path) -> Result<QueryBuilder, Error> {
+    let s = fs::read_to_string(path)
+        .map_err(|err| CliError::Config(format!("Could not read query {:?}: {}", path, err)))?;
+
+    let req = if is_toml(path) {
+        toml::from_str(&s).map_err(Error::from)
+    } else {
+        serde_json::from_str(&s).map_err(Error::from)
+    };
+
+    req.map_err(|err| CliError::Config(format!("Invalid query {:?}: {}", path, err)).into())
+}
+
+/// Reads a query saved with `save_query`, to be sent to `base_url`
+/// if set. Without one, the query must have been saved with a base
+/// url.
+pub fn load_query_for(path: &Path, base_url: Option<String>) -> Result<QueryBuilder, Error> {
+    let mut req = load_query(path)?;
+    match base_url {
+        Some(base_url) => { req.base_url(base_url); },
+        None if !req.has_base_url() => {
+            return Err(CliError::Config(format!(
+                "Base url must be supplied; the query saved in {:?} has none", path,
+            )).into());
+        },
+        None => (),
+    }
+    Ok(req)
+}
+
+/// Writes the query as toml if the file name ends in `.toml`,
+/// and as json otherwise.
+pub fn save_query(path: &Path, req: &QueryBuilder) -> Result<(), Error> {
+    let s = if is_toml(path) {
+        toml::to_string(req)?
+    } else {
+        serde_json::to_string_pretty(req)?
+    };
+
+    fs::write(path, s)
+        .map_err(|err| CliError::Config(format!("Could not write query {:?}: {}", path, err)).into())
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension().map(|ext| ext == "toml").unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mondrian_rest::error::{exit_code, EXIT_CONFIG};
+    use mondrian_rest::test_support::fixture_dir;
+
+    #[test]
+    fn test_parse_query_file() {
+        let queries = parse(r#"
+            [[query]]
+            name = "exports by county"
+            cube = "exports"
+            drilldowns = ["Geography.County", "Year.Year"]
+            measures = ["Dollars Sum"]
+            cuts = ["Year.Year.2016"]
+            parents = true
+
+            [[query]]
+            name = "big states"
+            cube = "exports"
+            named_sets = ["Big States"]
+            measures = ["Count"]
+            format = "csv"
+            output = "out/big-states.csv"
+        "#).unwrap();
+
+        assert_eq!(queries.len(), 2);
+        assert_eq!(queries[1].named_sets, vec!["Big States".to_owned()]);
+        assert_eq!(queries[1].output, Some(PathBuf::from("out/big-states.csv")));
+        assert_eq!(
+            *queries[1].query_builder("http://localhost:5000").unwrap().response_format(),
+            ResponseFormat::Csv
+        );
+        assert!(!queries[1].parents);
+
+        let url = queries[0].query_builder("http://localhost:5000").unwrap().url().unwrap();
+        assert_eq!(
+            url.as_str(),
+            "http://localhost:5000/cubes/exports/aggregate.json?\
+            drilldown%5B%5D=%5BGeography%5D.%5BGeography%5D.%5BCounty%5D&\
+            drilldown%5B%5D=%5BYear%5D.%5BYear%5D.%5BYear%5D&\
+            measures%5B%5D=Dollars+Sum&\
+            cut%5B%5D=%5BYear%5D.%5BYear%5D.%5BYear%5D.%26%5B2016%5D&\
+            debug=false&parents=true&nonempty=false&distinct=false&sparse=false"
+        );
+    }
+
+    #[test]
+    fn test_parse_query_file_errors() {
+        let err = |s: &str| parse(s).unwrap_err().to_string();
+
+        assert_eq!(
+            err(r#"
+                [[query]]
+                name = "q"
+                cube = "exports"
+                drilldowns = ["Geography.County"]
+                measures = ["Count"]
+
+                [[query]]
+                name = "q"
+                cube = "imports"
+                drilldowns = ["Geography.County"]
+                measures = ["Count"]
+            "#),
+            r#"query name "q" is used more than once"#
+        );
+
+        assert_eq!(
+            err(r#"
+                [[query]]
+                name = "q"
+                cube = "exports"
+                measures = ["Count"]
+            "#),
+            r#"query "q" needs a drilldown (or named set) and a measure"#
+        );
+
+        assert_eq!(
+            err(r#"
+                [[query]]
+                name = " "
+                cube = "exports"
+                drilldowns = ["Geography.County"]
+                measures = ["Count"]
+            "#),
+            "query name can't be empty"
+        );
+
+        // typo in a field
+        assert!(err(r#"
+            [[query]]
+            name = "q"
+            cube = "exports"
+            drilldowns = ["Geography.County"]
+            measure = ["Count"]
+        "#).contains("unknown field `measure`"));
+
+        assert_eq!(err(""), "no [[query]] entries");
+
+        let bad_name = parse(r#"
+            [[query]]
+            name = "q"
+            cube = "exports"
+            drilldowns = ["[Geography"]
+            measures = ["Count"]
+        "#).unwrap();
+        let err = bad_name[0].query_builder("http://localhost:5000").unwrap_err().to_string();
+        assert!(err.starts_with(r#"In query "q": "#), "{}", err);
+
+        let bad_format = parse(r#"
+            [[query]]
+            name = "q"
+            cube = "exports"
+            drilldowns = ["Geography.County"]
+            measures = ["Count"]
+            format = "xml"
+        "#).unwrap();
+        let err = bad_format[0].query_builder("http://localhost:5000").unwrap_err().to_string();
+        assert!(err.starts_with(r#"In query "q": "#) && err.contains("xml"), "{}", err);
+    }
+
+    #[test]
+    fn test_load_query_for() {
+        let dir = fixture_dir("load-query-for");
+        fs::create_dir_all(&dir).unwrap();
+        let with_base_url = dir.join("with-base-url.json");
+        let without_base_url = dir.join("without-base-url.json");
+        fs::write(&with_base_url, r#"{ "base_url": "http://saved", "cube": "exports" }"#).unwrap();
+        fs::write(&without_base_url, r#"{ "cube": "exports" }"#).unwrap();
+
+        assert!(load_query_for(&with_base_url, None).unwrap().has_base_url());
+        let req = load_query_for(&with_base_url, Some("http://cli".to_owned())).unwrap();
+        assert!(req.url().unwrap().as_str().starts_with("http://cli/"));
+        assert!(load_query_for(&without_base_url, Some("http://cli".to_owned())).is_ok());
+
+        let err = load_query_for(&without_base_url, None).unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_CONFIG);
+        assert!(err.to_string().starts_with("Base url must be supplied"));
+    }
+
+    #[test]
+    fn test_save_load_query() {
+        let dir = fixture_dir("saved-query");
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut req = api::query("http://localhost:5000".to_owned());
+        req.cube("exports")
+            .drilldown("Geography.County".parse().unwrap())
+            .measure("Dollars Sum".parse().unwrap())
+            .cut("Year.Year.2016".parse().unwrap())
+            .sparse(true)
+            .format(ResponseFormat::Csv);
+
+        for name in &["q.json", "q.toml"] {
+            let path = dir.join(name);
+            save_query(&path, &req).unwrap();
+            assert_eq!(load_query(&path).unwrap(), req);
+        }
+
+        let toml = fs::read_to_string(dir.join("q.toml")).unwrap();
+        assert!(toml.contains(r#"drilldowns = ["[Geography].[Geography].[County]"]"#));
+
+        fs::write(dir.join("typo.json"), r#"{ "cube": "exports", "drilldown": ["Year.Year"] }"#).unwrap();
+        assert!(load_query(&dir.join("typo.json")).is_err());
+        assert!(load_query(&dir.join("missing.json")).is_err());
+    }
+}
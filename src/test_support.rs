@@ -0,0 +1,39 @@
+//! Fixtures shared by the tests of the library and the binary.
+//!
+//! Not part of the api: it's only public so that the binary's
+//! tests can use it.
+
+use reqwest::{Client, StatusCode, Url};
+use serde_json;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use schema::CubeDescription;
+use transport::{self, Mode, Response, Transport};
+
+/// Description of the `exports` cube, used throughout the tests
+pub const EXPORTS_JSON: &str = include_str!("schema/exports.json");
+
+pub fn exports() -> CubeDescription {
+    serde_json::from_str(EXPORTS_JSON).unwrap()
+}
+
+/// Empty fixture directory, unique to the test
+pub fn fixture_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("mondrian-rest-cli-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+/// Transport replaying the given responses
+pub fn replay(name: &str, responses: &[(&str, u16, &str)]) -> Transport {
+    let dir = fixture_dir(name);
+    for &(url, status, body) in responses {
+        let url = Url::parse(url).unwrap();
+        let resp = Response { status: StatusCode::try_from(status).unwrap(), body: body.to_owned() };
+        transport::save_fixture(&dir, &url, &resp).unwrap();
+    }
+    Transport::new(Client::new(), Mode::Replay(dir))
+}
@@ -0,0 +1,74 @@
+//! Bisection of a failing list (e.g. measures of a test query),
+//! to find which items cause the failure.
+
+/// Finds the items which cause `fails`, given that `fails(items)`
+/// is already known to be true.
+///
+/// The list is split in halves, and each failing half is split
+/// again until single items are left. If neither half fails on its
+/// own, the failure depends on the combination, so the whole list
+/// is returned.
+pub fn bisect<T, F>(items: &[T], fails: &mut F) -> Vec<T>
+    where T: Clone,
+          F: FnMut(&[T]) -> bool,
+{
+    if items.len() <= 1 {
+        return items.to_vec();
+    }
+
+    let (left, right) = items.split_at(items.len() / 2);
+
+    let mut culprits = Vec::new();
+    if fails(left) {
+        culprits.extend(bisect(left, fails));
+    }
+    if fails(right) {
+        culprits.extend(bisect(right, fails));
+    }
+
+    if culprits.is_empty() {
+        items.to_vec()
+    } else {
+        culprits
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bisect_one() {
+        let items: Vec<u32> = (0..8).collect();
+        let mut calls = 0;
+        let culprits = bisect(&items, &mut |subset: &[u32]| {
+            calls += 1;
+            subset.contains(&5)
+        });
+
+        assert_eq!(culprits, vec![5]);
+        // two queries per level, 3 levels
+        assert_eq!(calls, 6);
+    }
+
+    #[test]
+    fn test_bisect_many() {
+        let items: Vec<u32> = (0..7).collect();
+        let culprits = bisect(&items, &mut |subset: &[u32]| {
+            subset.iter().any(|i| *i == 0 || *i == 6)
+        });
+
+        assert_eq!(culprits, vec![0, 6]);
+    }
+
+    #[test]
+    fn test_bisect_combination() {
+        // fails only when both 1 and 2 are present
+        let items: Vec<u32> = (0..4).collect();
+        let culprits = bisect(&items, &mut |subset: &[u32]| {
+            subset.contains(&1) && subset.contains(&2)
+        });
+
+        assert_eq!(culprits, items);
+    }
+}
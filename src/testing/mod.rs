@@ -0,0 +1,765 @@
+//! Testing cubes by sending queries generated from
+//! the cube schema, and collecting the result of each.
+//!
+//! A failing query doesn't stop the run; all results are
+//! collected so that a summary can be shown at the end,
+//! and written to reports for CI (see report module).
+
+pub mod bisect;
+pub mod pool;
+pub mod report;
+mod sample;
+pub mod timing;
+
+use failure::Error;
+use reqwest::Url;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use mondrian_rest::api::{self, QueryBuilder};
+use mondrian_rest::api::names::{Drilldown, Measure, Property};
+use mondrian_rest::error::CliError;
+use mondrian_rest::schema::{CubeDescription, Test};
+use mondrian_rest::Client;
+use self::bisect::bisect;
+use self::pool::{run_parallel, RateLimiter};
+
+/// Which levels to drill down on when testing a cube.
+/// Quick is the default; thorough covers every level of
+/// every hierarchy, which is slower on large schemas.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Strategy {
+    #[default]
+    Quick,
+    Thorough,
+}
+
+impl FromStr for Strategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "quick" => Ok(Strategy::Quick),
+            "thorough" => Ok(Strategy::Thorough),
+            _ => Err(CliError::Config(format!("{:?} is not a valid test strategy; expected quick or thorough", s)).into()),
+        }
+    }
+}
+
+/// Options for a test run
+#[derive(Debug, Default)]
+pub struct TestOptions {
+    pub strategy: Strategy,
+    /// Bisect the measures of a failing query
+    pub bisect: bool,
+    /// Also bisect properties
+    pub bisect_all: bool,
+    /// Also query each level cut on its first and last member
+    pub sample_cuts: bool,
+    /// Number of queries to run at once
+    pub jobs: usize,
+    /// Shared by all workers, applied to every request
+    pub rate_limit: RateLimiter,
+    /// Queries taking longer are flagged as slow
+    pub slow_threshold: Option<Duration>,
+    /// Print a latency table for each cube
+    pub timings: bool,
+    pub verbose: bool,
+}
+
+/// A query generated for testing. The lists are kept apart
+/// from the rest of the query so that they can be bisected
+/// when the query fails.
+#[derive(Debug, Clone)]
+pub struct TestQuery {
+    /// What the query tests, e.g. `drilldown [Geography].[County]`
+    pub name: String,
+    /// Cube, named sets, and anything else not bisected
+    pub req: QueryBuilder,
+    pub drilldowns: Vec<Drilldown>,
+    pub measures: Vec<Measure>,
+    pub properties: Vec<Property>,
+}
+
+impl TestQuery {
+    fn build(&self, drilldowns: &[Drilldown], measures: &[Measure], properties: &[Property]) -> QueryBuilder {
+        let mut req = self.req.clone();
+        req.drilldowns(drilldowns.to_vec())
+            .measures(measures.to_vec())
+            .properties(properties.to_vec());
+        req
+    }
+
+    pub fn query_builder(&self) -> QueryBuilder {
+        self.build(&self.drilldowns, &self.measures, &self.properties)
+    }
+}
+
+/// Result of one test query
+#[derive(Debug)]
+pub struct TestResult {
+    pub cube_name: String,
+    /// What the query tests, e.g. `drilldown [Geography].[County]`
+    pub name: String,
+    /// The query url, or a description of what was being
+    /// tested if no url could be built
+    pub query: String,
+    /// Drilldowns and properties of the query, for the latency table
+    pub drilldowns: Vec<Drilldown>,
+    pub properties: Vec<Property>,
+    pub duration: Duration,
+    /// Formatted error (all causes), if the query failed
+    pub error: Option<String>,
+    /// Parts of the query found by bisection to cause the error,
+    /// e.g. `measure Dollars Sum`
+    pub culprits: Vec<String>,
+    /// Response size in bytes, if there was a response
+    pub size: Option<usize>,
+    /// Took longer than the slow threshold
+    pub slow: bool,
+}
+
+impl TestResult {
+    /// Not yet run; passed until an error is set
+    fn new<S: Into<String>>(cube_name: &str, name: S, query: String) -> Self {
+        TestResult {
+            cube_name: cube_name.to_owned(),
+            name: name.into(),
+            query,
+            drilldowns: Vec::new(),
+            properties: Vec::new(),
+            duration: Duration::default(),
+            error: None,
+            culprits: Vec::new(),
+            size: None,
+            slow: false,
+        }
+    }
+
+    fn with_error(mut self, err: &Error) -> Self {
+        self.error = Some(format_error(err));
+        self
+    }
+
+    /// Sets the duration, and marks as slow if over the threshold
+    fn timed(&mut self, duration: Duration, options: &TestOptions) {
+        self.duration = duration;
+        self.slow = options.slow_threshold.map(|threshold| duration > threshold).unwrap_or(false);
+    }
+
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Summary of failures across all cubes tested
+pub struct Summary<'a> {
+    pub results: &'a [TestResult],
+}
+
+impl<'a> Summary<'a> {
+    pub fn failed(&self) -> usize {
+        self.results.iter().filter(|res| !res.passed()).count()
+    }
+
+    pub fn slow(&self) -> usize {
+        self.results.iter().filter(|res| res.slow).count()
+    }
+}
+
+impl<'a> fmt::Display for Summary<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let failed = self.failed();
+        let slow = self.slow();
+        write!(f, "Test summary: {} queries, {} failed", self.results.len(), failed)?;
+        if slow > 0 {
+            write!(f, ", {} slow", slow)?;
+        }
+        writeln!(f)?;
+
+        if failed > 0 {
+            writeln!(f, "Failures:")?;
+        }
+        for res in self.results.iter().filter(|res| !res.passed()) {
+            writeln!(f, "  {}: {}", res.cube_name, res.query)?;
+            if let Some(ref err) = res.error {
+                for line in err.lines() {
+                    writeln!(f, "    {}", line)?;
+                }
+            }
+            for culprit in &res.culprits {
+                writeln!(f, "    caused by {}", culprit)?;
+            }
+        }
+
+        if slow > 0 {
+            writeln!(f, "Slow queries:")?;
+        }
+        for res in self.results.iter().filter(|res| res.slow) {
+            writeln!(f, "  {}: {} ({:.3}s)", res.cube_name, res.name, res.duration.as_secs_f64())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Formats all causes of an error, one per line
+pub fn format_error(err: &Error) -> String {
+    err.causes()
+        .map(|cause| cause.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Work for one worker: a test query, or fetching the members
+/// of a level and testing sample cuts on them.
+enum Work<'a> {
+    Query(&'a str, Box<TestQuery>),
+    SampleCuts(&'a str, Drilldown, &'a [Measure]),
+}
+
+/// Tests all cubes, running up to `options.jobs` queries at once.
+/// Results are in the same order as if run one at a time.
+pub fn test_cubes(client: &Client,
+    cube_descriptions: &[CubeDescription],
+    options: &TestOptions,
+    ) -> Vec<TestResult>
+{
+    // names must outlive the work that borrows from them
+    let names: Vec<_> = cube_descriptions.iter()
+        .map(|cube_description| test_names(cube_description, options))
+        .collect();
+
+    let mut work = Vec::new();
+    let mut schema_errors = Vec::new();
+
+    for (cube_description, names) in cube_descriptions.iter().zip(&names) {
+        let cube_name = cube_description.name.as_str();
+
+        let names = match *names {
+            Ok(ref names) => names,
+            Err(ref err) => {
+                schema_errors.push(
+                    TestResult::new(cube_name, "schema", "generating test queries from schema".to_owned())
+                        .with_error(err)
+                );
+                continue;
+            },
+        };
+
+        for query in test_queries(cube_name, names, client.base_url()) {
+            work.push(Work::Query(cube_name, Box::new(query)));
+        }
+        if options.sample_cuts {
+            for drilldown in &names.levels {
+                work.push(Work::SampleCuts(cube_name, drilldown.clone(), &names.measures));
+            }
+        }
+    }
+
+    let mut results: Vec<_> = run_parallel(&work, options.jobs, |work| {
+            match *work {
+                Work::Query(cube_name, ref query) => {
+                    vec![run_test(client, cube_name, query, options)]
+                },
+                Work::SampleCuts(cube_name, ref drilldown, measures) => {
+                    sample::test_sample_cuts(client, cube_name, drilldown, measures, options)
+                },
+            }
+        })
+        .into_iter()
+        .flatten()
+        .collect();
+    results.extend(schema_errors);
+
+    // group by cube, keeping the order of cubes
+    let mut ordered = Vec::with_capacity(results.len());
+    for cube_description in cube_descriptions {
+        let (cube_results, rest): (Vec<_>, Vec<_>) = results.into_iter()
+            .partition(|res| res.cube_name == cube_description.name);
+        results = rest;
+
+        print_cube_results(&cube_description.name, &cube_results, options);
+        if options.timings {
+            println!("{}", timing::Timings { cube_name: &cube_description.name, results: &cube_results });
+        }
+        ordered.extend(cube_results);
+    }
+
+    ordered
+}
+
+fn print_cube_results(cube_name: &str, results: &[TestResult], options: &TestOptions) {
+    let failed = results.iter().filter(|res| !res.passed()).count();
+    if results.iter().any(|res| res.name == "schema") {
+        println!("{}: failed to generate test queries", cube_name);
+    } else if failed == 0 {
+        println!("{}: passed", cube_name);
+    } else {
+        println!("{}: {} of {} queries failed", cube_name, failed, results.len());
+    }
+
+    // thorough testing is per level, so show where failures are
+    if options.strategy == Strategy::Thorough {
+        for res in results {
+            let status = if res.passed() { "ok" } else { "FAILED" };
+            println!("  {:<6} {}", status, res.name);
+        }
+    }
+}
+
+/// Executes a test request, within the rate limit
+fn exec(client: &Client, url: Url, options: &TestOptions) -> Result<String, Error> {
+    exec_timed(client, url, options).0
+}
+
+/// Like `exec`, also returning how long the request took. Waiting
+/// for the rate limit doesn't count, so that a rate limit doesn't
+/// make queries look slow.
+fn exec_timed(client: &Client, url: Url, options: &TestOptions) -> (Result<String, Error>, Duration) {
+    options.rate_limit.wait();
+    let start = Instant::now();
+    let resp = client.get(url);
+    (resp, start.elapsed())
+}
+
+fn run_test(client: &Client, cube_name: &str, query: &TestQuery, options: &TestOptions) -> TestResult {
+    let req = query.query_builder();
+
+    let url = match req.url() {
+        Ok(url) => url,
+        Err(err) => {
+            return TestResult::new(cube_name, query.name.clone(), format!("{:?}", req))
+                .with_error(&err);
+        },
+    };
+
+    if options.verbose {
+        println!("Test url:\n{}\n", url);
+    }
+
+    let mut result = TestResult::new(cube_name, query.name.clone(), url.to_string());
+    result.drilldowns = query.drilldowns.clone();
+    result.properties = query.properties.clone();
+
+    let (resp, duration) = exec_timed(client, url, options);
+    result.timed(duration, options);
+
+    match resp {
+        Ok(body) => result.size = Some(body.len()),
+        Err(err) => {
+            result = result.with_error(&err);
+            if options.bisect {
+                result.culprits = find_culprits(client, query, options);
+            }
+        },
+    }
+
+    result
+}
+
+/// Bisects the measures of a failing query, and (with bisect_all)
+/// its properties, with the measures narrowed to their culprits.
+/// Drilldowns aren't bisected: each test query has only one.
+fn find_culprits(client: &Client, query: &TestQuery, options: &TestOptions) -> Vec<String> {
+    let fails = |drilldowns: &[Drilldown], measures: &[Measure], properties: &[Property]| {
+        let url = match query.build(drilldowns, measures, properties).url() {
+            Ok(url) => url,
+            Err(_) => return true,
+        };
+        if options.verbose {
+            println!("Bisect url:\n{}\n", url);
+        }
+        exec(client, url, options).is_err()
+    };
+
+    let mut culprits = Vec::new();
+
+    let mut measure_fails = |measures: &[Measure]| fails(&query.drilldowns, measures, &query.properties);
+
+    // The halves are tried first: if the query fails with either
+    // half, the error doesn't depend on the measures (e.g. a bad
+    // drilldown), and bisecting them would blame all of them.
+    // A single measure can't be told apart from the rest of the
+    // query, so it isn't blamed.
+    let measures = if query.measures.len() > 1 {
+        let (left, right) = query.measures.split_at(query.measures.len() / 2);
+        match (measure_fails(left), measure_fails(right)) {
+            (true, true) => {
+                culprits.push("not the measures (the query fails with either half of them)".to_owned());
+                left.to_vec()
+            },
+            (false, false) => {
+                culprits.push("all measures (no smaller set of measures isolates the error)".to_owned());
+                query.measures.clone()
+            },
+            (left_fails, _) => {
+                let half = if left_fails { left } else { right };
+                let measures = bisect(half, &mut measure_fails);
+                culprits.extend(measures.iter().map(|measure| format!("measure {}", measure)));
+                measures
+            },
+        }
+    } else {
+        query.measures.clone()
+    };
+
+    if !options.bisect_all {
+        return culprits;
+    }
+
+    // properties are only at fault if the query passes without them
+    if !query.properties.is_empty() && !fails(&query.drilldowns, &measures, &[]) {
+        let properties = bisect(&query.properties, &mut |properties: &[Property]| {
+            fails(&query.drilldowns, &measures, properties)
+        });
+        culprits.extend(properties.iter().map(|property| format!("property {}", property)));
+    }
+
+    culprits
+}
+
+/// Parsed names from the schema to test with
+struct TestNames {
+    drilldowns: Vec<Drilldown>,
+    /// Every level below the all level, whatever the strategy,
+    /// for sample cuts
+    levels: Vec<Drilldown>,
+    measures: Vec<Measure>,
+    properties: Vec<Property>,
+    named_sets: Vec<String>,
+}
+
+fn test_names(cube_description: &CubeDescription, options: &TestOptions) -> Result<TestNames, Error> {
+    let test_drill_mea_prop = match options.strategy {
+        Strategy::Quick => cube_description.test_drill_mea_prop(),
+        // short names, since mondrian's full names for non-default
+        // hierarchies (`[Dim.Hier].[Level]`) can't be parsed
+        Strategy::Thorough => Test {
+            name: cube_description.name.clone(),
+            dims: cube_description.level_names(),
+            meas: cube_description.measure_names(),
+            props: cube_description.property_names(),
+            named_sets: cube_description.named_set_names(),
+        },
+    };
+    if options.verbose {
+        println!("{}", test_drill_mea_prop);
+    }
+
+    Ok(TestNames {
+        drilldowns: test_drill_mea_prop.dims.iter()
+            .map(|s| s.parse())
+            .collect::<Result<Vec<Drilldown>, Error>>()?,
+        levels: cube_description.level_names().iter()
+            .map(|s| s.parse())
+            .collect::<Result<Vec<Drilldown>, Error>>()?,
+        measures: test_drill_mea_prop.meas.iter()
+            .map(|s| s.parse())
+            .collect::<Result<Vec<Measure>, Error>>()?,
+        properties: test_drill_mea_prop.props.iter()
+            .map(|s| s.parse())
+            .collect::<Result<Vec<Property>, Error>>()?,
+        named_sets: test_drill_mea_prop.named_sets,
+    })
+}
+
+/// To prevent all combinations being tested:
+/// - each level (depending on strategy) with all measures
+/// - each property (and its level) with all measures
+/// - each named set with all measures
+///
+/// Each query is named for what it tests.
+fn test_queries(cube_name: &str, names: &TestNames, base_url: &str) -> Vec<TestQuery> {
+    let mut base = api::query(base_url.to_owned());
+    base.cube(cube_name);
+
+    let mut queries = Vec::new();
+
+    for drilldown in &names.drilldowns {
+        queries.push(TestQuery {
+            name: format!("drilldown {}", drilldown),
+            req: base.clone(),
+            drilldowns: vec![drilldown.clone()],
+            measures: names.measures.clone(),
+            properties: Vec::new(),
+        });
+    }
+
+    for property in &names.properties {
+        queries.push(TestQuery {
+            name: format!("property {}", property),
+            req: base.clone(),
+            drilldowns: vec![property.drill_level()],
+            measures: names.measures.clone(),
+            properties: vec![property.clone()],
+        });
+    }
+
+    for named_set in &names.named_sets {
+        let mut req = base.clone();
+        req.named_set(named_set.clone());
+        queries.push(TestQuery {
+            name: format!("named set {}", named_set),
+            req,
+            drilldowns: Vec::new(),
+            measures: names.measures.clone(),
+            properties: Vec::new(),
+        });
+    }
+
+    queries
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use mondrian_rest::test_support::{exports, replay};
+
+    /// One passed and one failed query
+    pub(crate) fn results() -> Vec<TestResult> {
+        vec![
+            TestResult {
+                cube_name: "exports".to_owned(),
+                name: "drilldown [Year].[Year].[Year]".to_owned(),
+                query: "http://localhost/cubes/exports/aggregate.json?a".to_owned(),
+                drilldowns: vec!["Year.Year".parse().unwrap()],
+                properties: Vec::new(),
+                duration: Duration::from_millis(250),
+                error: None,
+                culprits: Vec::new(),
+                size: Some(2048),
+                slow: false,
+            },
+            TestResult {
+                cube_name: "exports".to_owned(),
+                name: "drilldown [Geography].[Geography].[County]".to_owned(),
+                query: "http://localhost/cubes/exports/aggregate.json?b".to_owned(),
+                drilldowns: vec!["Geography.County".parse().unwrap()],
+                properties: vec!["Geography.County.name_en".parse().unwrap()],
+                duration: Duration::from_millis(1500),
+                error: Some("[500 Internal Server Error] Mondrian error:\nbad column".to_owned()),
+                culprits: vec!["measure Dollars Sum".to_owned()],
+                size: None,
+                slow: true,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_cubes_replay() {
+        let base_url = "http://localhost:5000";
+        let cube = exports();
+        let options = TestOptions { bisect: true, ..TestOptions::default() };
+
+        let names = test_names(&cube, &options).unwrap();
+        let queries = test_queries("exports", &names, base_url);
+        let url = |query: &TestQuery, measures: &[Measure]| {
+            query.build(&query.drilldowns, measures, &query.properties).url().unwrap().to_string()
+        };
+
+        // the first query fails because of one of its measures
+        let error = r#"{"error": "Column 'dollars' not found"}"#;
+        let failing = &queries[0];
+        let mut responses = vec![
+            (url(failing, &failing.measures), 500, error),
+            (url(failing, &failing.measures[..1]), 500, error),
+            (url(failing, &failing.measures[1..]), 200, "{}"),
+        ];
+        for query in &queries[1..] {
+            responses.push((url(query, &query.measures), 200, "{}"));
+        }
+        let responses: Vec<_> = responses.iter()
+            .map(|&(ref url, status, body)| (url.as_str(), status, body))
+            .collect();
+        let client = Client::with_transport(replay("test-cubes", &responses), base_url);
+
+        let results = test_cubes(&client, &[cube], &options);
+        let summary = Summary { results: &results };
+
+        assert_eq!(results.len(), queries.len());
+        assert_eq!(summary.failed(), 1);
+        assert_eq!(results[0].name, failing.name);
+        assert!(results[0].error.as_ref().unwrap().contains("Column 'dollars' not found"));
+        assert_eq!(results[0].culprits, vec!["measure Dollars Sum".to_owned()]);
+        assert!(results[1..].iter().all(|res| res.passed() && res.size == Some(2)));
+    }
+
+    #[test]
+    fn test_culprits_not_measures() {
+        let base_url = "http://localhost:5000";
+        let names = test_names(&exports(), &TestOptions::default()).unwrap();
+        let query = test_queries("exports", &names, base_url).remove(0);
+        assert!(query.measures.len() > 1);
+
+        // every query fails, whatever its measures
+        let (left, right) = query.measures.split_at(query.measures.len() / 2);
+        let url = |measures: &[Measure]| {
+            query.build(&query.drilldowns, measures, &query.properties).url().unwrap().to_string()
+        };
+        let urls = [url(left), url(right)];
+        let error = r#"{"error": "Column 'county' not found"}"#;
+        let responses: Vec<_> = urls.iter().map(|url| (url.as_str(), 500, error)).collect();
+        let client = Client::with_transport(replay("culprits-not-measures", &responses), base_url);
+
+        let options = TestOptions { bisect: true, ..TestOptions::default() };
+        assert_eq!(
+            find_culprits(&client, &query, &options),
+            vec!["not the measures (the query fails with either half of them)".to_owned()]
+        );
+
+        // with one measure, there's nothing to narrow down
+        let mut single = query.clone();
+        single.measures.truncate(1);
+        assert!(find_culprits(&client, &single, &options).is_empty());
+    }
+
+    #[test]
+    fn test_culprits_property() {
+        let base_url = "http://localhost:5000";
+        let names = test_names(&exports(), &TestOptions::default()).unwrap();
+        let query = test_queries("exports", &names, base_url).into_iter()
+            .find(|query| !query.properties.is_empty())
+            .unwrap();
+        assert!(query.measures.len() > 1);
+
+        // fails with the property, whatever the measures
+        let (left, right) = query.measures.split_at(query.measures.len() / 2);
+        let url = |measures: &[Measure], properties: &[Property]| {
+            query.build(&query.drilldowns, measures, properties).url().unwrap().to_string()
+        };
+        let urls = [url(left, &query.properties), url(right, &query.properties), url(left, &[])];
+        let error = r#"{"error": "Column 'name_en' not found"}"#;
+        let client = Client::with_transport(
+            replay("culprits-property", &[(&urls[0], 500, error), (&urls[1], 500, error), (&urls[2], 200, "{}")]),
+            base_url,
+        );
+
+        let options = TestOptions { bisect: true, ..TestOptions::default() };
+        assert_eq!(
+            find_culprits(&client, &query, &options),
+            vec!["not the measures (the query fails with either half of them)".to_owned()]
+        );
+
+        let options = TestOptions { bisect: true, bisect_all: true, ..TestOptions::default() };
+        assert_eq!(
+            find_culprits(&client, &query, &options),
+            vec![
+                "not the measures (the query fails with either half of them)".to_owned(),
+                format!("property {}", query.properties[0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_duration_excludes_rate_limit() {
+        let base_url = "http://localhost:5000";
+        let names = test_names(&exports(), &TestOptions::default()).unwrap();
+        let query = test_queries("exports", &names, base_url).remove(0);
+
+        let url = query.query_builder().url().unwrap().to_string();
+        let client = Client::with_transport(replay("rate-limit", &[(&url, 200, "{}")]), base_url);
+
+        // the second query waits about 200ms for the rate limit
+        let options = TestOptions {
+            rate_limit: RateLimiter::new(Some(5.0)),
+            slow_threshold: Some(Duration::from_millis(100)),
+            ..TestOptions::default()
+        };
+        let start = Instant::now();
+        let results: Vec<_> = (0..2).map(|_| run_test(&client, "exports", &query, &options)).collect();
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        for res in &results {
+            assert!(res.passed());
+            assert!(res.duration < Duration::from_millis(100));
+            assert!(!res.slow);
+        }
+        assert_eq!(results[0].drilldowns, query.drilldowns);
+    }
+
+    #[test]
+    fn test_sample_cuts_quick() {
+        let base_url = "http://localhost:5000";
+        let members = r#"{
+            "name": "State",
+            "caption": "State",
+            "members": [{
+                "name": "Alaska", "full_name": "[Geography].[Alaska]", "caption": "Alaska",
+                "key": 2, "all_member?": false, "drillable?": true, "depth": 1,
+                "num_children": 0, "parent_name": "All Geographies", "level_name": "State", "children": []
+            }]
+        }"#;
+        let members_url = |level: &str| format!(
+            "{}/cubes/exports/dimensions/Geography/hierarchies/Geography/levels/{}/members", base_url, level,
+        );
+        let (state, county) = (members_url("State"), members_url("County"));
+        let client = Client::with_transport(
+            replay("sample-cuts-quick", &[(&state, 200, members), (&county, 200, members)]),
+            base_url,
+        );
+
+        // quick drills down on the all levels, but sample cuts are on real levels
+        let options = TestOptions { sample_cuts: true, ..TestOptions::default() };
+        let results = test_cubes(&client, &[exports()], &options);
+        let names: Vec<_> = results.iter().map(|res| res.name.as_str()).collect();
+
+        assert!(names.contains(&"members [Geography].[Geography].[State]"));
+        assert!(names.contains(&"members [Geography].[Geography].[County]"));
+        assert!(names.contains(&"cut [Geography].[Geography].[State].&[2]"));
+        assert!(names.contains(&"cut [Geography].[Geography].[County].&[2]"));
+        assert!(!names.iter().any(|name| name.contains("(All)") && name.starts_with("members")));
+
+        let fetched: Vec<_> = results.iter()
+            .filter(|res| res.name.starts_with("members [Geography]"))
+            .collect();
+        assert_eq!(fetched.len(), 2);
+        assert!(fetched.iter().all(|res| res.passed()));
+    }
+
+    #[test]
+    fn test_names_strategy() {
+        let cube = exports();
+
+        let quick = test_names(&cube, &TestOptions::default()).unwrap();
+        assert_eq!(quick.drilldowns.len(), 2);
+
+        let options = TestOptions { strategy: Strategy::Thorough, ..TestOptions::default() };
+        let thorough = test_names(&cube, &options).unwrap();
+        let level_names: Vec<_> = thorough.drilldowns.iter().map(|drilldown| drilldown.to_string()).collect();
+        assert_eq!(level_names, vec![
+            "[Geography].[Geography].[State]",
+            "[Geography].[Geography].[County]",
+            "[Year].[Year].[Year]",
+            "[Year].[Fiscal Year].[Fiscal Year]",
+        ]);
+        assert_eq!(thorough.properties.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_strategy() {
+        assert_eq!("quick".parse::<Strategy>().unwrap(), Strategy::Quick);
+        assert_eq!("thorough".parse::<Strategy>().unwrap(), Strategy::Thorough);
+        assert!("all".parse::<Strategy>().is_err());
+    }
+
+    #[test]
+    fn test_summary() {
+        let results = results();
+        let summary = Summary { results: &results };
+
+        assert_eq!(summary.failed(), 1);
+        assert_eq!(
+            summary.to_string(),
+            "Test summary: 2 queries, 1 failed, 1 slow\n\
+             Failures:\n  \
+             exports: http://localhost/cubes/exports/aggregate.json?b\n    \
+             [500 Internal Server Error] Mondrian error:\n    \
+             bad column\n    \
+             caused by measure Dollars Sum\n\
+             Slow queries:\n  \
+             exports: drilldown [Geography].[Geography].[County] (1.500s)\n"
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! Bounded concurrency for test queries.
+//!
+//! Work is handed out to a fixed number of worker threads, and
+//! results are returned in the order of the input, so that output
+//! and reports don't depend on scheduling. An optional rate limit
+//! spaces out requests across all workers.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Applies `f` to each item on `jobs` threads (at least one),
+/// returning results in the same order as the items.
+pub fn run_parallel<T, R, F>(items: &[T], jobs: usize, f: F) -> Vec<R>
+    where T: Sync,
+          R: Send,
+          F: Fn(&T) -> R + Sync,
+{
+    let jobs = jobs.max(1).min(items.len());
+    if jobs <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    if i >= items.len() {
+                        break;
+                    }
+                    let res = f(&items[i]);
+                    results.lock().unwrap()[i] = Some(res);
+                }
+            });
+        }
+    });
+
+    results.into_inner().unwrap()
+        .into_iter()
+        // every index is taken by exactly one worker
+        .map(|res| res.unwrap())
+        .collect()
+}
+
+/// Limits requests to a maximum rate, shared across threads.
+/// Without a rate, `wait` returns immediately.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    interval: Option<Duration>,
+    next: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    /// Rate in requests per second
+    pub fn new(rate: Option<f64>) -> Self {
+        RateLimiter {
+            interval: rate
+                .filter(|rate| *rate > 0.0)
+                .map(|rate| Duration::from_secs_f64(1.0 / rate)),
+            next: Mutex::new(None),
+        }
+    }
+
+    /// Blocks until the next request is allowed
+    pub fn wait(&self) {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return,
+        };
+
+        // reserve a slot while holding the lock, sleep after releasing it
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let slot = match *next {
+                Some(next) if next > now => next,
+                _ => now,
+            };
+            *next = Some(slot + interval);
+            slot
+        };
+
+        let now = Instant::now();
+        if slot > now {
+            thread::sleep(slot - now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_run_parallel_order() {
+        let items: Vec<u64> = (0..50).collect();
+
+        // later items finish first
+        let results = run_parallel(&items, 8, |i| {
+            thread::sleep(Duration::from_millis(50 - *i));
+            i * 2
+        });
+
+        assert_eq!(results, items.iter().map(|i| i * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_run_parallel_sequential() {
+        let items = vec!["a", "b"];
+        assert_eq!(run_parallel(&items, 0, |s| s.to_uppercase()), vec!["A", "B"]);
+        assert!(run_parallel(&Vec::<u8>::new(), 4, |i| *i).is_empty());
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(Some(100.0));
+        let start = Instant::now();
+        for _ in 0..5 {
+            limiter.wait();
+        }
+        // first request is immediate, then 4 intervals of 10ms
+        assert!(start.elapsed() >= Duration::from_millis(40));
+
+        let unlimited = RateLimiter::new(None);
+        let start = Instant::now();
+        for _ in 0..100 {
+            unlimited.wait();
+        }
+        assert!(start.elapsed() < Duration::from_millis(40));
+    }
+}
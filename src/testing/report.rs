@@ -0,0 +1,243 @@
+//! Reports of test results for CI servers.
+//!
+//! - junit: one testsuite per cube, one testcase per query
+//! - json: flat list of test cases
+//!
+//! Selected with `--report <kind>=<path>`.
+
+use failure::Error;
+use serde_json;
+use std::collections::BTreeMap;
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use mondrian_rest::error::CliError;
+use super::TestResult;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Report {
+    Junit(PathBuf),
+    Json(PathBuf),
+}
+
+impl Report {
+    pub fn path(&self) -> &PathBuf {
+        match *self {
+            Report::Junit(ref path) | Report::Json(ref path) => path,
+        }
+    }
+
+    pub fn render(&self, results: &[TestResult]) -> Result<String, Error> {
+        match *self {
+            Report::Junit(_) => Ok(junit(results)),
+            Report::Json(_) => json(results),
+        }
+    }
+
+    pub fn write(&self, results: &[TestResult]) -> Result<(), Error> {
+        let out = self.render(results)?;
+        let mut f = File::create(self.path())
+            .map_err(|err| format_err!("Could not create report {:?}: {}", self.path(), err))?;
+        f.write_all(out.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl FromStr for Report {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '=');
+        let kind = parts.next().unwrap_or("");
+        let path = match parts.next() {
+            Some(path) if !path.is_empty() => PathBuf::from(path),
+            _ => return Err(CliError::Config(format!("Report {:?} must be <kind>=<path>", s)).into()),
+        };
+
+        match kind {
+            "junit" => Ok(Report::Junit(path)),
+            "json" => Ok(Report::Json(path)),
+            _ => Err(CliError::Config(format!("{:?} is not a valid report kind; expected junit or json", kind)).into()),
+        }
+    }
+}
+
+fn junit(results: &[TestResult]) -> String {
+    // keep cubes in the order they were tested
+    let mut cubes: Vec<&str> = Vec::new();
+    let mut by_cube: BTreeMap<&str, Vec<&TestResult>> = BTreeMap::new();
+    for res in results {
+        if !by_cube.contains_key(res.cube_name.as_str()) {
+            cubes.push(&res.cube_name);
+        }
+        by_cube.entry(&res.cube_name).or_default().push(res);
+    }
+
+    let total_time: Duration = results.iter().map(|res| res.duration).sum();
+    let total_failures = results.iter().filter(|res| !res.passed()).count();
+
+    // write! to a String can't fail
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(out,
+        "<testsuites name=\"mondrian-rest-cli\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">",
+        results.len(), total_failures, total_time.as_secs_f64());
+
+    for cube in cubes {
+        let cases = &by_cube[cube];
+        let time: Duration = cases.iter().map(|res| res.duration).sum();
+        let failures = cases.iter().filter(|res| !res.passed()).count();
+
+        let _ = writeln!(out,
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">",
+            escape_xml(cube), cases.len(), failures, time.as_secs_f64());
+
+        for res in cases {
+            let _ = writeln!(out,
+                "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\">",
+                escape_xml(&res.name), escape_xml(cube), res.duration.as_secs_f64());
+            if let Some(ref err) = res.error {
+                let message = err.lines().next().unwrap_or("");
+                let mut text = err.clone();
+                for culprit in &res.culprits {
+                    text.push_str("\ncaused by ");
+                    text.push_str(culprit);
+                }
+                let _ = writeln!(out,
+                    "      <failure message=\"{}\">{}</failure>",
+                    escape_xml(message), escape_xml(&text));
+            }
+            let _ = writeln!(out, "      <system-out>{}</system-out>", escape_xml(&res.query));
+            out.push_str("    </testcase>\n");
+        }
+
+        out.push_str("  </testsuite>\n");
+    }
+
+    out.push_str("</testsuites>\n");
+    out
+}
+
+fn escape_xml(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[derive(Debug, Serialize)]
+struct JsonReport<'a> {
+    tests: usize,
+    failures: usize,
+    cases: Vec<JsonCase<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonCase<'a> {
+    cube: &'a str,
+    name: &'a str,
+    url: &'a str,
+    duration_secs: f64,
+    passed: bool,
+    error: Option<&'a str>,
+    culprits: &'a [String],
+    size_bytes: Option<usize>,
+    slow: bool,
+}
+
+fn json(results: &[TestResult]) -> Result<String, Error> {
+    let report = JsonReport {
+        tests: results.len(),
+        failures: results.iter().filter(|res| !res.passed()).count(),
+        cases: results.iter()
+            .map(|res| {
+                JsonCase {
+                    cube: &res.cube_name,
+                    name: &res.name,
+                    url: &res.query,
+                    duration_secs: res.duration.as_secs_f64(),
+                    passed: res.passed(),
+                    error: res.error.as_deref(),
+                    culprits: &res.culprits,
+                    size_bytes: res.size,
+                    slow: res.slow,
+                }
+            })
+            .collect(),
+    };
+
+    Ok(serde_json::to_string_pretty(&report)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::Value;
+    use testing::test::results;
+
+    #[test]
+    fn test_parse_report() {
+        assert_eq!(
+            "junit=target/report.xml".parse::<Report>().unwrap(),
+            Report::Junit(PathBuf::from("target/report.xml"))
+        );
+        assert_eq!(
+            "json=out=1.json".parse::<Report>().unwrap(),
+            Report::Json(PathBuf::from("out=1.json"))
+        );
+        assert!("junit".parse::<Report>().is_err());
+        assert!("junit=".parse::<Report>().is_err());
+        assert!("html=report.html".parse::<Report>().is_err());
+    }
+
+    #[test]
+    fn test_junit() {
+        let xml = junit(&results());
+
+        assert!(xml.contains(r#"<testsuites name="mondrian-rest-cli" tests="2" failures="1" time="1.750">"#));
+        assert!(xml.contains(r#"<testsuite name="exports" tests="2" failures="1" time="1.750">"#));
+        assert!(xml.contains(
+            r#"<testcase name="drilldown [Geography].[Geography].[County]" classname="exports" time="1.500">"#
+        ));
+        assert!(xml.contains(
+            "<failure message=\"[500 Internal Server Error] Mondrian error:\">\
+            [500 Internal Server Error] Mondrian error:\nbad column\n\
+            caused by measure Dollars Sum</failure>"
+        ));
+        assert!(xml.contains("<system-out>http://localhost/cubes/exports/aggregate.json?a</system-out>"));
+        assert_eq!(xml.matches("<failure").count(), 1);
+    }
+
+    #[test]
+    fn test_escape_xml() {
+        assert_eq!(escape_xml(r#"a<b & "c">"#), "a&lt;b &amp; &quot;c&quot;&gt;");
+    }
+
+    #[test]
+    fn test_json() {
+        let report: Value = serde_json::from_str(&json(&results()).unwrap()).unwrap();
+
+        assert_eq!(report["tests"], 2);
+        assert_eq!(report["failures"], 1);
+        assert_eq!(report["cases"][0]["cube"], "exports");
+        assert_eq!(report["cases"][0]["error"], Value::Null);
+        assert_eq!(report["cases"][0]["size_bytes"], 2048);
+        assert_eq!(report["cases"][1]["slow"], true);
+        assert_eq!(report["cases"][1]["url"], "http://localhost/cubes/exports/aggregate.json?b");
+        assert_eq!(report["cases"][1]["duration_secs"], 1.5);
+        assert_eq!(report["cases"][1]["passed"], false);
+        assert_eq!(report["cases"][1]["culprits"][0], "measure Dollars Sum");
+    }
+}
@@ -0,0 +1,135 @@
+//! Sample cut testing: cuts each level on real members, since
+//! some schema bugs (e.g. a wrong key column) only show up when
+//! a cut is applied.
+//!
+//! Members are fetched with the members endpoint, and the level
+//! is cut on the key of its first and last member.
+
+use failure::Error;
+use serde_json;
+
+use mondrian_rest::api::{Cut, Drilldown, LevelName, Measure};
+use mondrian_rest::error::CliError;
+use mondrian_rest::schema::{Key, Members};
+use mondrian_rest::Client;
+use super::{exec_timed, run_test, TestOptions, TestQuery, TestResult};
+
+pub(super) fn test_sample_cuts(client: &Client,
+    cube_name: &str,
+    drilldown: &Drilldown,
+    measures: &[Measure],
+    options: &TestOptions,
+    ) -> Vec<TestResult>
+{
+    let level_name = drilldown.level_name();
+
+    let mut result = TestResult::new(cube_name, format!("members {}", level_name), String::new());
+    result.drilldowns = vec![drilldown.clone()];
+
+    let members = match fetch_members(client, cube_name, level_name, options, &mut result) {
+        Ok(members) => members,
+        Err(err) => return vec![result.with_error(&err)],
+    };
+
+    let mut results = vec![result];
+
+    for key in members.sample_keys() {
+        let cut = match round_trip_cut(level_name, key) {
+            Ok(cut) => cut,
+            Err(err) => {
+                let name = format!("cut {}.&[{}]", level_name, key);
+                results.push(TestResult::new(cube_name, name, String::new()).with_error(&err));
+                continue;
+            },
+        };
+
+        let mut req = client.query();
+        req.cube(cube_name)
+            .cut(cut.clone());
+
+        let query = TestQuery {
+            name: format!("cut {}", cut),
+            req,
+            drilldowns: vec![drilldown.clone()],
+            measures: measures.to_vec(),
+            properties: Vec::new(),
+        };
+        results.push(run_test(client, cube_name, &query, options));
+    }
+
+    results
+}
+
+/// Fills in the url, duration, and size of the members test result
+fn fetch_members(client: &Client,
+    cube_name: &str,
+    level_name: &LevelName,
+    options: &TestOptions,
+    result: &mut TestResult,
+    ) -> Result<Members, Error>
+{
+    let mut req = client.query();
+    req.cube(cube_name)
+        .members(level_name.clone());
+
+    let url = req.url()?;
+    if options.verbose {
+        println!("Members url:\n{}\n", url);
+    }
+    result.query = url.to_string();
+
+    let (resp, duration) = exec_timed(client, url, options);
+    result.timed(duration, options);
+
+    let resp = resp?;
+    result.size = Some(resp.len());
+
+    Ok(serde_json::from_str(&resp)?)
+}
+
+/// Cut on one member key, checking that rendering the cut
+/// and parsing it back gives the same cut.
+fn round_trip_cut(level_name: &LevelName, key: &Key) -> Result<Cut, Error> {
+    let cut = Cut::new(
+        level_name.dimension(),
+        level_name.hierarchy(),
+        level_name.level(),
+        vec![key.to_string().as_str()],
+    );
+
+    let rendered = cut.to_string();
+    let parsed = rendered.parse::<Cut>()?;
+
+    if parsed != cut {
+        return Err(CliError::Name(format!(
+            "Cut {} does not round trip for key {:?}; parsed as {:?}",
+            rendered, key, parsed,
+        )).into());
+    }
+
+    Ok(cut)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_cut() {
+        let level_name = LevelName::new("Geography", "Geography", "County");
+
+        let keys = vec![
+            Key::Int(6001),
+            Key::Int(-1),
+            Key::String("06001".to_owned()),
+            Key::String("St. Louis, MO".to_owned()),
+            Key::String("[bracketed]".to_owned()),
+            Key::String("&amp".to_owned()),
+        ];
+
+        for key in &keys {
+            let cut = round_trip_cut(&level_name, key).unwrap();
+            assert_eq!(cut.members(), &[key.to_string()]);
+        }
+    }
+}
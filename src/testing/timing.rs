@@ -0,0 +1,91 @@
+//! Latency table of the test queries of one cube, slowest first,
+//! to find where aggregate tables would help.
+
+use std::cmp::Reverse;
+use std::fmt;
+
+use super::TestResult;
+
+pub struct Timings<'a> {
+    pub cube_name: &'a str,
+    pub results: &'a [TestResult],
+}
+
+/// Names joined with ", ", or "-" if there are none
+fn join<T: fmt::Display>(names: &[T]) -> String {
+    if names.is_empty() {
+        return "-".to_owned();
+    }
+    names.iter()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl<'a> fmt::Display for Timings<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut results: Vec<&TestResult> = self.results.iter().collect();
+        results.sort_by_key(|res| Reverse(res.duration));
+
+        let rows: Vec<[String; 5]> = results.iter()
+            .map(|res| {
+                [
+                    join(&res.drilldowns),
+                    join(&res.properties),
+                    format!("{:.3}s", res.duration.as_secs_f64()),
+                    res.size.map(|size| size.to_string()).unwrap_or_else(|| "-".to_owned()),
+                    if res.slow { "SLOW".to_owned() } else { "".to_owned() },
+                ]
+            })
+            .collect();
+
+        let header = ["drilldown", "property", "duration", "bytes", ""];
+        let mut widths: Vec<usize> = header.iter().map(|cell| cell.chars().count()).collect();
+        for row in &rows {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(cell.chars().count());
+            }
+        }
+
+        // names left-aligned, numbers right-aligned
+        let write_row = |f: &mut fmt::Formatter, cells: &[&str]| -> fmt::Result {
+            let line = format!(
+                "  {:<w0$}  {:<w1$}  {:>w2$}  {:>w3$}  {}",
+                cells[0], cells[1], cells[2], cells[3], cells[4],
+                w0 = widths[0], w1 = widths[1], w2 = widths[2], w3 = widths[3],
+            );
+            writeln!(f, "{}", line.trim_end())
+        };
+
+        writeln!(f, "Timings for {} (slowest first):", self.cube_name)?;
+        write_row(f, &header)?;
+        let rule: Vec<String> = widths[..4].iter().map(|width| "-".repeat(*width)).collect();
+        writeln!(f, "  {}", rule.join("  "))?;
+        for row in &rows {
+            write_row(f, &[&row[0], &row[1], &row[2], &row[3], &row[4]])?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use testing::test::results;
+
+    #[test]
+    fn test_timings() {
+        let results = results();
+        let timings = Timings { cube_name: "exports", results: &results };
+
+        assert_eq!(
+            timings.to_string(),
+            "Timings for exports (slowest first):\n\
+            \x20 drilldown                         property                                    duration  bytes\n\
+            \x20 --------------------------------  ------------------------------------------  --------  -----\n\
+            \x20 [Geography].[Geography].[County]  [Geography].[Geography].[County].[name_en]    1.500s      -  SLOW\n\
+            \x20 [Year].[Year].[Year]              -                                             0.250s   2048\n"
+        );
+    }
+}
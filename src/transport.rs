@@ -0,0 +1,185 @@
+//! Sending requests to the server, live or from recorded fixtures.
+//!
+//! - Live: requests go to the server.
+//! - Record: requests go to the server, and each response
+//!   (status and body) is saved in a fixture directory.
+//! - Replay: responses are read from the fixture directory, and
+//!   nothing is sent over the network.
+//!
+//! Fixtures are json files named by a hash of the url, so that
+//! replaying the same command finds the same responses. The flush
+//! secret is redacted from the url before it's hashed or saved.
+
+use failure::Error;
+use reqwest::{Client, StatusCode, Url};
+use serde_json;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use error::CliError;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Mode {
+    #[default]
+    Live,
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+/// Response status and body, before checking for errors
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Fixture {
+    url: String,
+    status: u16,
+    body: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Transport {
+    client: Client,
+    mode: Mode,
+}
+
+impl Transport {
+    pub fn new(client: Client, mode: Mode) -> Self {
+        Transport { client, mode }
+    }
+
+    pub fn get(&self, url: Url) -> Result<Response, Error> {
+        match self.mode {
+            Mode::Live => self.send(url),
+            Mode::Record(ref dir) => {
+                let resp = self.send(url.clone())?;
+                save_fixture(dir, &url, &resp)?;
+                Ok(resp)
+            },
+            Mode::Replay(ref dir) => load_fixture(dir, &redact(&url)),
+        }
+    }
+
+    fn send(&self, url: Url) -> Result<Response, Error> {
+        let mut resp = self.client.get(url).send()?;
+        Ok(Response {
+            status: resp.status(),
+            body: resp.text()?,
+        })
+    }
+}
+
+/// Url with the value of the flush secret replaced
+fn redact(url: &Url) -> String {
+    if !url.query_pairs().any(|(key, _)| key == "secret") {
+        return url.to_string();
+    }
+
+    let pairs: Vec<(String, String)> = url.query_pairs()
+        .map(|(key, value)| {
+            let value = if key == "secret" { "REDACTED".to_owned() } else { value.into_owned() };
+            (key.into_owned(), value)
+        })
+        .collect();
+
+    let mut url = url.clone();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs);
+    url.to_string()
+}
+
+fn fixture_path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(format!("{:016x}.json", fnv1a(key.as_bytes())))
+}
+
+/// Saves a response for replay, as if it was recorded
+/// (e.g. to write fixtures for tests by hand).
+pub fn save_fixture(dir: &Path, url: &Url, resp: &Response) -> Result<(), Error> {
+    let key = redact(url);
+    fs::create_dir_all(dir)
+        .map_err(|err| CliError::Config(format!("Could not create fixture dir {:?}: {}", dir, err)))?;
+
+    let fixture = Fixture {
+        url: key.clone(),
+        status: resp.status.as_u16(),
+        body: resp.body.clone(),
+    };
+    fs::write(fixture_path(dir, &key), serde_json::to_string_pretty(&fixture)?)?;
+
+    Ok(())
+}
+
+fn load_fixture(dir: &Path, key: &str) -> Result<Response, Error> {
+    let path = fixture_path(dir, key);
+
+    let s = fs::read_to_string(&path)
+        .map_err(|_| CliError::Transport(format!("No recorded response for {} in {:?}", key, dir)))?;
+    let fixture: Fixture = serde_json::from_str(&s)
+        .map_err(|err| CliError::Config(format!("Invalid fixture {:?}: {}", path, err)))?;
+
+    // hash collision, or a hand-edited fixture
+    if fixture.url != key {
+        return Err(CliError::Transport(format!("No recorded response for {} in {:?}", key, dir)).into());
+    }
+
+    let status = StatusCode::try_from(fixture.status)
+        .map_err(|_| CliError::Config(format!("Invalid status {} in fixture {:?}", fixture.status, path)))?;
+
+    Ok(Response { status, body: fixture.body })
+}
+
+/// 64-bit FNV-1a; std's hasher isn't guaranteed to be stable
+/// across releases, and fixtures may be checked in.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use error::{exit_code, EXIT_TRANSPORT};
+    use test_support::replay;
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn test_redact() {
+        let url = Url::parse("http://localhost:5000/flush?secret=hunter2").unwrap();
+        assert_eq!(redact(&url), "http://localhost:5000/flush?secret=REDACTED");
+
+        let url = Url::parse("http://localhost:5000/cubes/exports/aggregate.json?measures%5B%5D=Count").unwrap();
+        assert_eq!(redact(&url), url.as_str());
+    }
+
+    #[test]
+    fn test_replay() {
+        let transport = replay("replay", &[
+            ("http://localhost:5000/cubes", 200, r#"{"cubes": []}"#),
+            ("http://localhost:5000/flush?secret=hunter2", 403, "Forbidden"),
+        ]);
+
+        let resp = transport.get(Url::parse("http://localhost:5000/cubes").unwrap()).unwrap();
+        assert_eq!(resp, Response { status: StatusCode::Ok, body: r#"{"cubes": []}"#.to_owned() });
+
+        // any secret matches the redacted fixture
+        let resp = transport.get(Url::parse("http://localhost:5000/flush?secret=other").unwrap()).unwrap();
+        assert_eq!(resp.status, StatusCode::Forbidden);
+
+        let err = transport.get(Url::parse("http://localhost:5000/cubes/exports").unwrap()).unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_TRANSPORT);
+    }
+}